@@ -1,10 +1,18 @@
 use crate::error::Result;
-use crate::ThemeMonitor;
+use crate::logging::log_info;
+use crate::{Theme, ThemeMonitor};
+
+/// Called with the current theme once the monitor is running
+pub type StartedHook = Box<dyn FnOnce(Theme) + Send>;
 
 /// Platform-agnostic application trait that can be implemented for different operating systems
 pub trait Application {
-    /// Run the application event loop
-    fn run(self: Box<Self>) -> Result<()>;
+    /// Run the application event loop, calling `on_started` once the monitor
+    /// has started
+    fn run(self: Box<Self>, on_started: StartedHook) -> Result<()>;
+
+    /// Query the theme currently reported by the platform
+    fn get_current_theme(&self) -> Theme;
 }
 
 /// Generic application runner that uses the platform-specific implementation
@@ -18,14 +26,14 @@ impl<M: ThemeMonitor> ApplicationRunner<M> {
     }
     
     pub fn run(self) -> Result<()> {
-        println!("Starting theme monitor...");
+        log_info("Starting theme monitor...");
         
         // Print initial theme
-        println!("Current theme: {}", self.monitor.get_current_theme());
+        log_info(&format!("Current theme: {}", self.monitor.get_current_theme()));
         
         // Start monitoring
         self.monitor.start()?;
-        println!("Monitoring for theme changes. Press Ctrl+C to stop.");
+        log_info("Monitoring for theme changes. Press Ctrl+C to stop.");
         
         // Note: The actual event loop will be handled by platform-specific implementations
         // This is just the common setup logic
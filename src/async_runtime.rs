@@ -1,172 +1,209 @@
-use std::error::Error;
+use std::path::PathBuf;
 use std::sync::Arc;
+use futures_util::{Stream, StreamExt};
 use tokio::runtime::Runtime;
-use crate::{cli::Args, config, config_file::Config, handlers::*, ipc::*, lua_handler::LuaHandler};
-use crate::app::Application;
+use crate::{config_file::Config, logging, handlers::ThemeHandler, ipc::IpcServer, platform};
+use crate::app::StartedHook;
+use crate::error::{Result, ThemeSwitcherError};
+use crate::handlers::{HandlerOutcome, HandlerReport};
+use crate::events::{self, EventSender, ThemeEvent, ThemeEvents, ThemeSource};
+use crate::state::{self, AppliedState};
+use crate::worker::HandlerWorker;
 
-pub fn run_with_tokio(args: Args) -> Result<(), Box<dyn Error>> {
-    // Create tokio runtime
-    let runtime = Runtime::new()?;
-    
-    runtime.block_on(async {
-        run_async(args).await
-    })
-}
+/// Runs the daemon: the platform event loop keeps the main thread while
+/// theme events are dispatched to the handler on the tokio runtime
+pub fn run(
+    runtime: Runtime,
+    sender: EventSender,
+    events: ThemeEvents,
+    handler: Arc<dyn ThemeHandler>,
+    ipc_server: Option<IpcServer>,
+    config: Option<&Config>,
+    apply_on_start: bool,
+) -> Result<()> {
+    let app = platform::create_application(sender.clone(), config)?;
 
-async fn run_async(args: Args) -> Result<(), Box<dyn Error>> {
-    // Load config file if specified
-    let config = if let Some(ref config_path) = args.config {
-        config::log_info(&format!("Loading config from: {:?}", config_path));
-        Some(Config::load_from_file(config_path)?)
-    } else {
-        // Try to load from default location
-        let config_path = dirs::home_dir()
-            .map(|home| home.join(".config/theme-switcher/config.toml"))
-            .unwrap_or_default();
-        
-        if config_path.exists() {
-            config::log_info(&format!("Loading config from: {:?}", config_path));
-            Config::load_from_file(&config_path).ok()
-        } else {
+    // If IPC is enabled, set initial theme
+    if let Some(ref server) = ipc_server {
+        server.set_current_theme(app.get_current_theme());
+    }
+
+    let state_file = config
+        .and_then(|c| c.general.state_file.clone())
+        .unwrap_or_else(state::default_state_file);
+    let applied = match AppliedState::load(&state_file) {
+        Ok(applied) => applied,
+        Err(e) => {
+            logging::log_warn(&format!("Ignoring state file {:?}: {}", state_file, e));
             None
         }
     };
-    
-    // Set quiet mode globally (command line takes precedence)
-    let quiet = args.quiet || config.as_ref().map(|c| c.general.quiet).unwrap_or(false);
-    config::set_quiet_mode(quiet);
-    
-    let mut composite = CompositeThemeHandler::new();
-    
-    // Add logging handler unless in quiet mode
-    if !quiet {
-        composite.add_handler(Arc::new(LoggingThemeHandler));
-    }
-    
-    // Setup IPC if requested (command line takes precedence over config)
-    let ipc_enabled = args.ipc || config.as_ref().map(|c| c.general.ipc).unwrap_or(false);
-    let ipc_server = if ipc_enabled {
-        let server = IpcServer::new()?;
-        server.start().await?;
-        
-        // Add IPC handler to composite
-        composite.add_handler(Arc::new(IpcHandler::new(
-            server.get_broadcaster(),
-            server.get_current_theme_state()
-        )));
-        
-        Some(server)
-    } else {
+
+    // With a recorded state, the startup theme is compared against what was
+    // last applied, so handlers only run if it changed while the daemon was
+    // down or the last run failed. Applying on start always runs them.
+    let current = if apply_on_start {
         None
+    } else if let Some(ref applied) = applied {
+        applied.applied_theme()
+    } else {
+        Some(app.get_current_theme())
     };
-    
-    // Create script handler combining CLI args and config
-    let mut script_handler = ScriptHandler::new();
-    let mut has_scripts = false;
-    
-    // Add scripts from command line
-    if let Some(ref light_script) = args.light_script {
-        script_handler = script_handler.with_light_script(light_script.clone());
-        has_scripts = true;
-    }
-    if let Some(ref dark_script) = args.dark_script {
-        script_handler = script_handler.with_dark_script(dark_script.clone());
-        has_scripts = true;
-    }
-    if let Some(ref any_script) = args.any_script {
-        script_handler = script_handler.with_any_change_script(any_script.clone());
-        has_scripts = true;
-    }
-    
-    // Add scripts from config file
-    if let Some(ref cfg) = config {
-        if !cfg.scripts.light.is_empty() {
-            script_handler = script_handler.with_light_scripts(cfg.scripts.light.clone());
-            has_scripts = true;
+    let reconcile = apply_on_start || applied.is_some();
+
+    // Drop repeated themes and coalesce bursts of flips
+    let debounce = config.map(|c| c.general.debounce).unwrap_or_default();
+    let events = events::filter(events, current, debounce);
+
+    runtime.spawn(async move {
+        // The platform loop never returns, so exit from here even if
+        // dispatching panicked
+        let result = tokio::spawn(dispatch(Box::pin(events), handler, state_file)).await;
+
+        // Cleanup IPC if it was started
+        if let Some(ref server) = ipc_server {
+            server.cleanup();
         }
-        if !cfg.scripts.dark.is_empty() {
-            script_handler = script_handler.with_dark_scripts(cfg.scripts.dark.clone());
-            has_scripts = true;
+        let code = match result {
+            Ok(Ok(())) => 0,
+            Ok(Err(e)) => {
+                logging::log_error(&e.to_string());
+                e.exit_code().into()
+            }
+            Err(_) => 1,
+        };
+        std::process::exit(code);
+    });
+
+    let on_started: StartedHook = if reconcile {
+        Box::new(move |theme| sender.send(ThemeEvent::new(theme, ThemeSource::Startup)))
+    } else {
+        Box::new(|_| {})
+    };
+
+    // Run the app
+    app.run(on_started)?;
+
+    Ok(())
+}
+
+/// Passes each theme event to the handler until a shutdown signal arrives,
+/// recording the outcome in `state_file`. Stops with an error if a script or
+/// Lua hook fails on the startup event.
+pub async fn dispatch<S>(mut events: S, handler: Arc<dyn ThemeHandler>, state_file: PathBuf) -> Result<()>
+where
+    S: Stream<Item = ThemeEvent> + Unpin,
+{
+    let shutdown = shutdown_signal();
+    tokio::pin!(shutdown);
+    let (startup_failed, mut startup_failure) = tokio::sync::mpsc::unbounded_channel();
+
+    // Handlers run scripts synchronously, so they get their own thread where
+    // a newer event cancels the run in progress
+    let worker = HandlerWorker::spawn(handler, move |event, reports| {
+        for report in &reports {
+            logging::log_debug(&format!("Handler {} for the {} theme: {:?}", report.handler, event.theme, report.outcome));
         }
-        if !cfg.scripts.any.is_empty() {
-            script_handler = script_handler.with_any_change_scripts(cfg.scripts.any.clone());
-            has_scripts = true;
+        let failure = (event.source == ThemeSource::Startup).then(|| hook_failure(&reports)).flatten();
+        let applied = AppliedState::new(event, reports);
+        if let Err(e) = applied.save(&state_file) {
+            logging::log_error(&format!("Failed to write state file {:?}: {}", state_file, e));
         }
-    }
-    
-    if has_scripts {
-        composite.add_handler(Arc::new(script_handler));
-    }
-    
-    // Create Lua handler combining CLI args and config
-    let mut lua_handler = LuaHandler::new()?;
-    let mut has_lua_scripts = false;
-    
-    // Add Lua scripts from command line
-    if let Some(lua_light) = args.lua_light {
-        lua_handler = lua_handler.with_light_script(lua_light);
-        has_lua_scripts = true;
-    }
-    if let Some(lua_dark) = args.lua_dark {
-        lua_handler = lua_handler.with_dark_script(lua_dark);
-        has_lua_scripts = true;
-    }
-    if let Some(lua_any) = args.lua_any {
-        lua_handler = lua_handler.with_any_change_script(lua_any);
-        has_lua_scripts = true;
-    }
-    
-    // Add Lua scripts from config file
-    if let Some(ref cfg) = config {
-        if !cfg.lua_scripts.light.is_empty() {
-            lua_handler = lua_handler.with_light_scripts(cfg.lua_scripts.light.clone());
-            has_lua_scripts = true;
-        }
-        if !cfg.lua_scripts.dark.is_empty() {
-            lua_handler = lua_handler.with_dark_scripts(cfg.lua_scripts.dark.clone());
-            has_lua_scripts = true;
+        if let Some(failure) = failure {
+            let _ = startup_failed.send(failure);
         }
-        if !cfg.lua_scripts.any.is_empty() {
-            lua_handler = lua_handler.with_any_change_scripts(cfg.lua_scripts.any.clone());
-            has_lua_scripts = true;
+    });
+
+    let mut result = Ok(());
+
+    loop {
+        tokio::select! {
+            event = events.next() => {
+                let Some(event) = event else {
+                    // The monitor is gone; keep running until asked to stop
+                    shutdown.await;
+                    break;
+                };
+
+                logging::log_debug(&format!("Applying {} theme from {}", event.theme, event.source));
+                worker.submit(event);
+            }
+            Some(failure) = startup_failure.recv() => {
+                result = Err(failure);
+                break;
+            }
+            _ = &mut shutdown => break,
         }
     }
+
+    logging::log_info("Shutting down");
     
-    if has_lua_scripts {
-        composite.add_handler(Arc::new(lua_handler));
+    // Terminates hooks still running for the last change
+    if let Err(e) = tokio::task::spawn_blocking(move || worker.shutdown()).await {
+        logging::log_error(&format!("Theme handler failed: {}", e));
     }
-    
-    // Create the app with handlers
-    let handler = Arc::new(composite);
-    
-    // Create the application
-    #[cfg(target_os = "macos")]
-    {
-        use crate::platform::MacOSApplication;
-        let app = MacOSApplication::new(handler)?;
-        
-        // If IPC is enabled, set initial theme
-        if let Some(ref server) = ipc_server {
-            let current_theme = app.get_current_theme();
-            server.set_current_theme(current_theme);
+    result
+}
+
+/// The error for the first script or Lua hook that failed or timed out
+fn hook_failure(reports: &[HandlerReport]) -> Option<ThemeSwitcherError> {
+    reports.iter().find_map(|report| {
+        let error = match report.outcome {
+            HandlerOutcome::Failed { ref error } | HandlerOutcome::TimedOut { ref error } => error.clone(),
+            HandlerOutcome::Success | HandlerOutcome::Cancelled => return None,
+        };
+        match report.handler.as_str() {
+            "scripts" => Some(ThemeSwitcherError::Script(error)),
+            "lua" => Some(ThemeSwitcherError::Lua(error)),
+            _ => None,
         }
-        
-        // Run the app
-        Box::new(app).run()?;
-        
-        // Cleanup IPC if it was started
-        if let Some(ref server) = ipc_server {
-            server.cleanup();
+    })
+}
+
+/// Resolves on Ctrl+C, or SIGTERM on Unix
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(e) => logging::log_error(&format!("Failed to listen for SIGTERM: {}", e)),
         }
     }
-    
-    #[cfg(not(target_os = "macos"))]
-    {
-        use crate::error::ThemeSwitcherError;
-        return Err(Box::new(ThemeSwitcherError::PlatformError(
-            "This platform is not currently supported".to_string()
-        )));
+
+    if let Err(e) = tokio::signal::ctrl_c().await {
+        logging::log_error(&format!("Failed to listen for Ctrl+C: {}", e));
+        std::future::pending::<()>().await;
     }
-    
-    Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(handler: &str, outcome: HandlerOutcome) -> HandlerReport {
+        HandlerReport::new(handler, outcome)
+    }
+
+    #[test]
+    fn failing_hooks_map_to_their_error() {
+        let failed = || HandlerOutcome::Failed { error: "exit status 1".to_string() };
+        assert!(hook_failure(&[report("ipc", failed()), report("scripts", HandlerOutcome::Success)]).is_none());
+        assert!(hook_failure(&[report("scripts", HandlerOutcome::Cancelled)]).is_none());
+        assert!(matches!(
+            hook_failure(&[report("logging", HandlerOutcome::Success), report("scripts", failed())]),
+            Some(ThemeSwitcherError::Script(error)) if error == "exit status 1"
+        ));
+        assert!(matches!(
+            hook_failure(&[report("lua", HandlerOutcome::TimedOut { error: "slow".to_string() })]),
+            Some(ThemeSwitcherError::Lua(_))
+        ));
+    }
+}
@@ -1,3 +1,11 @@
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    theme_switcher::run()
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    match theme_switcher::run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            theme_switcher::logging::log_error(&format!("Error: {}", e));
+            ExitCode::from(e.exit_code())
+        }
+    }
 }
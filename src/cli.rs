@@ -1,18 +1,20 @@
-use clap::Parser;
+use chrono::NaiveDate;
+use clap::{ArgAction, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 pub struct Args {
-    /// Script to run when switching to light theme
+    /// Script to run when switching to light theme; run with `sh -c`, so it
+    /// may include arguments
     #[arg(short, long)]
     pub light_script: Option<PathBuf>,
     
-    /// Script to run when switching to dark theme
+    /// Script to run when switching to dark theme; run with `sh -c`
     #[arg(short, long)]
     pub dark_script: Option<PathBuf>,
     
-    /// Script to run on any theme change
+    /// Script to run on any theme change; run with `sh -c`
     #[arg(short, long)]
     pub any_script: Option<PathBuf>,
     
@@ -36,9 +38,50 @@ pub struct Args {
     #[arg(short, long)]
     pub quiet: bool,
     
+    /// Log more: -v for debug messages, -vv for trace messages
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+    
     /// Enable IPC server for Neovim integration
     #[arg(long)]
     pub ipc: bool,
+    
+    /// Run the handlers for the current theme once monitoring has started
+    #[arg(long)]
+    pub apply_on_start: bool,
+    
+    /// Stop on unknown config keys, or if the default config file is
+    /// invalid, instead of warning
+    #[arg(long)]
+    pub strict: bool,
+    
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print the sunrise and sunset switch times computed for a day
+    Sun {
+        /// Day to compute, as YYYY-MM-DD (defaults to today)
+        #[arg(long)]
+        date: Option<NaiveDate>,
+        
+        /// Latitude in degrees, overriding [solar] latitude
+        #[arg(long, allow_hyphen_values = true)]
+        latitude: Option<f64>,
+        
+        /// Longitude in degrees, overriding [solar] longitude
+        #[arg(long, allow_hyphen_values = true)]
+        longitude: Option<f64>,
+    },
+    
+    /// List the theme backends and which of them are available here
+    Backends,
+    
+    /// Validate the config file, the scripts it runs and the IPC socket
+    /// path; exits non-zero on problems
+    Check,
 }
 
 impl Args {
@@ -0,0 +1,418 @@
+use crate::cli::Command;
+use crate::config_file::{self, Config, HookCommand, ScriptHook, Twilight};
+use crate::error::{Result, ThemeSwitcherError};
+use crate::ipc;
+use crate::monitors::solar::{SolarDay, SolarSchedule};
+use crate::platform::registry;
+use chrono::{Local, NaiveDate};
+use mlua::Lua;
+use std::path::{Path, PathBuf};
+
+/// Run a one-shot subcommand
+pub fn run(command: &Command, config: Option<&Config>) -> Result<()> {
+    match command {
+        Command::Sun {
+            date,
+            latitude,
+            longitude,
+        } => sun(config, *date, *latitude, *longitude),
+        Command::Backends => backends(config),
+        Command::Check => check(config),
+    }
+}
+
+/// Counts the problems found by `check` while printing its report
+struct Report {
+    problems: usize,
+    /// Builds the error for problems in the current section
+    kind: fn(String) -> ThemeSwitcherError,
+    /// The kind of the first section with a problem, which sets the exit code
+    failed: Option<fn(String) -> ThemeSwitcherError>,
+}
+
+impl Report {
+    fn new() -> Self {
+        Self {
+            problems: 0,
+            kind: ThemeSwitcherError::config,
+            failed: None,
+        }
+    }
+
+    fn section(&mut self, title: &str, kind: fn(String) -> ThemeSwitcherError) {
+        self.kind = kind;
+        println!();
+        println!("{}:", title);
+    }
+
+    fn ok(&self, item: &str) {
+        println!("  ok     {}", item);
+    }
+
+    /// An error of the first failing section's kind if there were problems
+    fn finish(self) -> Result<()> {
+        match self.failed {
+            Some(kind) => Err(kind(format!("{} problem(s) found", self.problems))),
+            None => Ok(()),
+        }
+    }
+
+    fn problem(&mut self, item: &str, problem: &str) {
+        self.problems += 1;
+        self.failed.get_or_insert(self.kind);
+        println!("  error  {}: {}", item, problem);
+    }
+}
+
+/// Validate the config and everything it refers to without running any of
+/// it, for use in CI
+fn check(config: Option<&Config>) -> Result<()> {
+    let Some(config) = config else {
+        // A default config that failed to load was skipped; report why
+        if let Some(path) = config_file::default_config_paths().into_iter().find(|path| path.exists()) {
+            Config::load_from_file(&path, false)?;
+        }
+        let searched: Vec<String> = config_file::default_config_paths()
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        return Err(ThemeSwitcherError::config(format!(
+            "No config file to check (looked for {})",
+            searched.join(", ")
+        )));
+    };
+
+    let mut report = Report::new();
+
+    // The config already parsed, so only unknown keys are left to find
+    if let Some(ref path) = config.loaded_from {
+        println!("Config: {}", path.display());
+        let unknown = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|content| config_file::unknown_keys(&content).map_err(|e| e.message().to_string()));
+        match unknown {
+            Ok(keys) if keys.is_empty() => report.ok("no unknown keys"),
+            Ok(keys) => {
+                for key in keys {
+                    report.problem(&key, "unknown key");
+                }
+            }
+            Err(e) => report.problem(&path.display().to_string(), &e),
+        }
+    }
+
+    report.section("Scripts", ThemeSwitcherError::Script);
+    let scripts = &config.scripts;
+    let hooks: Vec<(&str, &ScriptHook)> = [("light", &scripts.light), ("dark", &scripts.dark), ("any", &scripts.any)]
+        .into_iter()
+        .flat_map(|(theme, entries)| {
+            entries
+                .iter()
+                .flat_map(|entry| entry.scripts())
+                .map(move |hook| (theme, hook))
+        })
+        .collect();
+    if hooks.is_empty() {
+        println!("  none configured");
+    }
+    for (theme, hook) in hooks {
+        let item = format!("{}: {}", theme, hook.name());
+        match check_hook(hook) {
+            Ok(()) => report.ok(&item),
+            Err(e) => report.problem(&item, &e),
+        }
+    }
+
+    report.section("Lua scripts", ThemeSwitcherError::Lua);
+    let lua_scripts = &config.lua_scripts;
+    let lua_paths: Vec<(&str, &PathBuf)> = [("light", &lua_scripts.light), ("dark", &lua_scripts.dark), ("any", &lua_scripts.any)]
+        .into_iter()
+        .flat_map(|(theme, paths)| paths.iter().map(move |path| (theme, path)))
+        .collect();
+    if lua_paths.is_empty() {
+        println!("  none configured");
+    }
+    let lua = Lua::new();
+    for (theme, path) in lua_paths {
+        let item = format!("{}: {}", theme, path.display());
+        match compile_lua(&lua, path) {
+            Ok(()) => report.ok(&item),
+            Err(e) => report.problem(&item, &e),
+        }
+    }
+
+    report.section("IPC socket", ThemeSwitcherError::Ipc);
+    let socket = ipc::socket_path();
+    let item = socket.display().to_string();
+    match check_socket(&socket) {
+        Ok(()) => report.ok(&item),
+        Err(e) => report.problem(&item, &e),
+    }
+
+    println!();
+    if report.problems == 0 {
+        println!("No problems found");
+    }
+    report.finish()
+}
+
+/// Checks that a hook's script or program can be run, without running it
+fn check_hook(hook: &ScriptHook) -> std::result::Result<(), String> {
+    match &hook.command {
+        HookCommand::Script { path, shell } => {
+            let metadata = std::fs::metadata(path).map_err(|e| e.to_string())?;
+            if !metadata.is_file() {
+                return Err("not a file".to_string());
+            }
+            // Executed even when started through a shell
+            if !accessible(path, Access::Execute) {
+                return Err("not executable".to_string());
+            }
+            if let Some(shell) = shell {
+                find_program(shell).map_err(|e| format!("shell {}", e))?;
+            }
+        }
+        HookCommand::Shell { shell, .. } => find_program(shell).map_err(|e| format!("shell {}", e))?,
+        HookCommand::Exec(args) => find_program(Path::new(&args[0]))?,
+    }
+
+    match hook.cwd {
+        Some(ref cwd) if !cwd.is_dir() => Err(format!("working directory {} does not exist", cwd.display())),
+        _ => Ok(()),
+    }
+}
+
+/// Checks that `program` is an executable path, or names one on `PATH`
+fn find_program(program: &Path) -> std::result::Result<(), String> {
+    let executable = |path: &Path| path.is_file() && accessible(path, Access::Execute);
+
+    if program.components().count() > 1 {
+        return if executable(program) {
+            Ok(())
+        } else {
+            Err(format!("{} is not an executable file", program.display()))
+        };
+    }
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    if std::env::split_paths(&path).any(|dir| executable(&dir.join(program))) {
+        Ok(())
+    } else {
+        Err(format!("{} not found in PATH", program.display()))
+    }
+}
+
+/// Compiles a Lua script without running it
+fn compile_lua(lua: &Lua, path: &Path) -> std::result::Result<(), String> {
+    let source = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    lua.load(&source)
+        .set_name(format!("@{}", path.display()))
+        .into_function()
+        .map(drop)
+        .map_err(|e| e.to_string())
+}
+
+/// Checks that the daemon could create its socket: the nearest existing
+/// directory on the way must be writable
+fn check_socket(socket: &Path) -> std::result::Result<(), String> {
+    let dir = socket
+        .ancestors()
+        .skip(1)
+        .find(|dir| dir.exists())
+        .ok_or("no parent directory exists")?;
+    if !dir.is_dir() {
+        return Err(format!("{} is not a directory", dir.display()));
+    }
+    if !accessible(dir, Access::Write) {
+        return Err(format!("{} is not writable", dir.display()));
+    }
+    if std::os::unix::net::UnixStream::connect(socket).is_ok() {
+        println!("         in use by a running instance");
+    }
+    Ok(())
+}
+
+enum Access {
+    Write,
+    Execute,
+}
+
+/// Whether this process may write to or execute `path`
+#[cfg(unix)]
+fn accessible(path: &Path, access: Access) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let mode = match access {
+        Access::Write => libc::W_OK,
+        Access::Execute => libc::X_OK,
+    };
+    let Ok(path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    // SAFETY: `path` is a valid NUL-terminated string
+    unsafe { libc::access(path.as_ptr(), mode) == 0 }
+}
+
+#[cfg(not(unix))]
+fn accessible(path: &Path, _access: Access) -> bool {
+    path.exists()
+}
+
+/// Print every backend with its detected availability and the one the
+/// configured preference list would pick
+fn backends(config: Option<&Config>) -> Result<()> {
+    let default_config = Config::default();
+    let config = config.unwrap_or(&default_config);
+
+    let preference = registry::resolve(&config.general.monitor)?;
+    let detected: Vec<_> = registry::backends()
+        .into_iter()
+        .map(|backend| {
+            let available = backend.detect(config).map_err(|e| e.to_string());
+            (backend, available)
+        })
+        .collect();
+
+    println!("Backends:");
+    for (backend, available) in &detected {
+        let status = match available {
+            Ok(()) => "available".to_string(),
+            Err(e) => format!("unavailable: {}", e),
+        };
+        println!("  {:<10} {}", backend.name, backend.description);
+        println!("  {:<10} {}", "", status);
+    }
+
+    let selected = preference.iter().find(|preferred| {
+        detected
+            .iter()
+            .any(|(backend, available)| backend.name == preferred.name && available.is_ok())
+    });
+    let names: Vec<&str> = preference.iter().map(|backend| backend.name).collect();
+
+    println!();
+    println!("Preference: {}", names.join(", "));
+    match selected {
+        Some(backend) => println!("Selected:   {}", backend.name),
+        None => println!("Selected:   none (no preferred backend is available)"),
+    }
+
+    Ok(())
+}
+
+/// Print the solar switch times for a day, for checking the `[solar]` setup
+fn sun(
+    config: Option<&Config>,
+    date: Option<NaiveDate>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+) -> Result<()> {
+    let mut solar = config.map(|c| c.solar.clone()).unwrap_or_default();
+    if latitude.is_some() {
+        solar.latitude = latitude;
+    }
+    if longitude.is_some() {
+        solar.longitude = longitude;
+    }
+
+    let schedule = SolarSchedule::from_config(&solar).map_err(|e| ThemeSwitcherError::config(e.to_string()))?;
+    let date = date.unwrap_or_else(|| Local::now().date_naive());
+
+    let twilight = match solar.twilight {
+        Twilight::Official => "official (sunrise/sunset)",
+        Twilight::Civil => "civil",
+        Twilight::Nautical => "nautical",
+    };
+
+    println!("Date:      {}", date);
+    println!(
+        "Location:  {:.4}, {:.4}",
+        solar.latitude.unwrap_or_default(),
+        solar.longitude.unwrap_or_default()
+    );
+    println!("Twilight:  {}", twilight);
+
+    const FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+    match schedule.day(date) {
+        SolarDay::Normal {
+            sunrise,
+            sunset,
+            light,
+            dark,
+        } => {
+            println!("Sunrise:   {}", sunrise.format(FORMAT));
+            println!("Sunset:    {}", sunset.format(FORMAT));
+            println!("Light at:  {} (offset {}s)", light.format(FORMAT), solar.sunrise_offset.num_seconds());
+            println!("Dark at:   {} (offset {}s)", dark.format(FORMAT), solar.sunset_offset.num_seconds());
+        }
+        SolarDay::AlwaysUp => println!("The sun stays up all day: light theme"),
+        SolarDay::AlwaysDown => println!("The sun stays down all day: dark theme"),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    fn script_with_mode(name: &str, mode: u32) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("theme-switcher-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    fn hook(path: PathBuf, shell: Option<&str>) -> ScriptHook {
+        ScriptHook {
+            command: HookCommand::Script {
+                path,
+                shell: shell.map(PathBuf::from),
+            },
+            env: Default::default(),
+            cwd: None,
+            timeout: None,
+        }
+    }
+
+    #[test]
+    fn accepts_executable_script() {
+        let path = script_with_mode("executable.sh", 0o755);
+        assert_eq!(check_hook(&hook(path.clone(), None)), Ok(()));
+        assert_eq!(check_hook(&hook(path, Some("sh"))), Ok(()));
+    }
+
+    #[test]
+    fn rejects_non_executable_script() {
+        let path = script_with_mode("not-executable.sh", 0o644);
+        assert_eq!(check_hook(&hook(path.clone(), None)), Err("not executable".to_string()));
+        assert_eq!(check_hook(&hook(path, Some("sh"))), Err("not executable".to_string()));
+    }
+
+    #[test]
+    fn rejects_missing_program() {
+        let hook = ScriptHook {
+            command: HookCommand::Exec(vec!["theme-switcher-no-such-program".to_string()]),
+            env: Default::default(),
+            cwd: None,
+            timeout: None,
+        };
+        assert_eq!(
+            check_hook(&hook),
+            Err("theme-switcher-no-such-program not found in PATH".to_string())
+        );
+    }
+
+    #[test]
+    fn check_reports_the_first_failing_section() {
+        let mut config = Config::default();
+        config.lua_scripts.dark = vec![PathBuf::from("/nonexistent/theme-switcher/dark.lua")];
+        assert!(matches!(check(Some(&config)), Err(ThemeSwitcherError::Lua(_))));
+
+        config.scripts.light = vec![script_with_mode("not-executable-check.sh", 0o644).into()];
+        assert!(matches!(check(Some(&config)), Err(ThemeSwitcherError::Script(_))));
+    }
+}
@@ -1,8 +1,18 @@
-use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use crate::Theme;
+use crate::error::{Result as ThemeResult, ThemeSwitcherError};
+use crate::logging::{Level, LogFormat, log_info, log_warn};
+use chrono::{NaiveTime, TimeDelta, Weekday};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct Config {
+    /// The file the config was loaded from
+    #[serde(skip)]
+    pub loaded_from: Option<PathBuf>,
+    
     #[serde(default)]
     pub general: GeneralConfig,
     
@@ -11,6 +21,24 @@ pub struct Config {
     
     #[serde(default)]
     pub lua_scripts: LuaScriptsConfig,
+    
+    #[serde(default)]
+    pub file: FileConfig,
+    
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    
+    #[serde(default)]
+    pub solar: SolarConfig,
+    
+    #[serde(default)]
+    pub ambient: AmbientConfig,
+    
+    #[serde(default)]
+    pub command: CommandConfig,
+    
+    #[serde(default)]
+    pub composite: CompositeConfig,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -18,26 +46,427 @@ pub struct GeneralConfig {
     #[serde(default)]
     pub quiet: bool,
     
+    /// Also write the log to this file
     #[serde(default)]
     pub log_file: Option<PathBuf>,
     
+    /// Most verbose messages to log: error, warn, info, debug or trace
+    #[serde(default)]
+    pub log_level: Level,
+    
+    /// `text` or `json` lines
+    #[serde(default)]
+    pub log_format: LogFormat,
+    
+    /// Size in bytes at which the log file is rotated; 0 never rotates it
+    #[serde(default = "default_log_max_size")]
+    pub log_max_size: u64,
+    
+    /// How many rotated log files are kept
+    #[serde(default = "default_log_max_files")]
+    pub log_max_files: usize,
+    
     #[serde(default)]
     pub ipc: bool,
+    
+    /// Run the handlers for the current theme once monitoring has started
+    #[serde(default)]
+    pub apply_on_start: bool,
+    
+    /// Where the last applied theme is recorded, see `state::default_state_file`
+    #[serde(default)]
+    pub state_file: Option<PathBuf>,
+    
+    /// Theme backends in order of preference, e.g. `"system"` or
+    /// `["portal", "gnome", "file"]`; see `theme-switcher backends`
+    #[serde(default = "default_monitor", deserialize_with = "deserialize_string_or_list")]
+    pub monitor: Vec<String>,
+    
+    /// Quiet period before a theme change is applied; flips within it are
+    /// coalesced so only the final theme runs the handlers
+    #[serde(default, with = "duration_format")]
+    pub debounce: Duration,
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        Self {
+            quiet: false,
+            log_file: None,
+            log_level: Level::default(),
+            log_format: LogFormat::default(),
+            log_max_size: default_log_max_size(),
+            log_max_files: default_log_max_files(),
+            ipc: false,
+            apply_on_start: false,
+            state_file: None,
+            monitor: default_monitor(),
+            debounce: Duration::ZERO,
+        }
+    }
+}
+
+fn default_log_max_size() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_log_max_files() -> usize {
+    5
+}
+
+fn default_monitor() -> Vec<String> {
+    vec![crate::platform::registry::SYSTEM.to_string()]
+}
+
+fn deserialize_string_or_list<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrList {
+        String(String),
+        List(Vec<String>),
+    }
+    
+    Ok(match StringOrList::deserialize(deserializer)? {
+        StringOrList::String(value) => vec![value],
+        StringOrList::List(values) => values,
+    })
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ScriptsConfig {
     #[serde(default)]
-    pub light: Vec<PathBuf>,
+    pub light: Vec<ScriptEntry>,
     
     #[serde(default)]
-    pub dark: Vec<PathBuf>,
+    pub dark: Vec<ScriptEntry>,
     
     #[serde(default)]
-    pub any: Vec<PathBuf>,
+    pub any: Vec<ScriptEntry>,
+    
+    /// How many entries may run at once
+    #[serde(default = "default_max_parallel")]
+    pub max_parallel: usize,
+    
+    /// How long a script may run before its process group is terminated;
+    /// unlimited when unset
+    #[serde(default, with = "optional_duration_format")]
+    pub timeout: Option<Duration>,
+    
+    /// How many bytes of each script's output are logged
+    #[serde(default = "default_output_limit")]
+    pub output_limit: usize,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+impl Default for ScriptsConfig {
+    fn default() -> Self {
+        Self {
+            light: Vec::new(),
+            dark: Vec::new(),
+            any: Vec::new(),
+            max_parallel: default_max_parallel(),
+            timeout: None,
+            output_limit: default_output_limit(),
+        }
+    }
+}
+
+fn default_max_parallel() -> usize {
+    1
+}
+
+fn default_output_limit() -> usize {
+    64 * 1024
+}
+
+/// A script, or a list of scripts that must run in order, e.g.
+/// `["terminal.sh", "tmux-reload.sh"]`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "ScriptEntryDefinition", into = "ScriptEntryDefinition")]
+pub enum ScriptEntry {
+    Script(ScriptHook),
+    Sequence(Vec<ScriptHook>),
+}
+
+impl ScriptEntry {
+    /// The entry's scripts in the order they run
+    pub fn scripts(&self) -> &[ScriptHook] {
+        match self {
+            ScriptEntry::Script(hook) => std::slice::from_ref(hook),
+            ScriptEntry::Sequence(hooks) => hooks,
+        }
+    }
+}
+
+impl From<PathBuf> for ScriptEntry {
+    fn from(path: PathBuf) -> Self {
+        ScriptEntry::Script(path.into())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ScriptEntryDefinition {
+    Sequence(Vec<ScriptHookDefinition>),
+    Script(ScriptHookDefinition),
+}
+
+impl TryFrom<ScriptEntryDefinition> for ScriptEntry {
+    type Error = String;
+    
+    fn try_from(definition: ScriptEntryDefinition) -> Result<Self, Self::Error> {
+        Ok(match definition {
+            ScriptEntryDefinition::Script(hook) => ScriptEntry::Script(hook.try_into()?),
+            ScriptEntryDefinition::Sequence(hooks) => ScriptEntry::Sequence(
+                hooks.into_iter().map(ScriptHook::try_from).collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+}
+
+impl From<ScriptEntry> for ScriptEntryDefinition {
+    fn from(entry: ScriptEntry) -> Self {
+        match entry {
+            ScriptEntry::Script(hook) => ScriptEntryDefinition::Script(hook.into()),
+            ScriptEntry::Sequence(hooks) => ScriptEntryDefinition::Sequence(hooks.into_iter().map(Into::into).collect()),
+        }
+    }
+}
+
+/// Shell that runs script paths and command strings unless a hook picks
+/// another one
+pub const DEFAULT_SHELL: &str = "sh";
+
+/// A script to run, given as a path or as a table such as
+/// `{ command = ["kitty", "@", "set-colors", "dark.conf"], timeout = "5s" }`
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(try_from = "ScriptHookDefinition", into = "ScriptHookDefinition")]
+pub struct ScriptHook {
+    pub command: HookCommand,
+    
+    /// Variables set in addition to the `THEME_SWITCHER_*` ones
+    pub env: HashMap<String, String>,
+    
+    /// Working directory, the daemon's when unset
+    pub cwd: Option<PathBuf>,
+    
+    /// Overrides `[scripts] timeout` for this script
+    pub timeout: Option<Duration>,
+}
+
+/// How a hook is started
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HookCommand {
+    /// A script run directly, or by `<shell>` with the path as `$0`
+    Script { path: PathBuf, shell: Option<PathBuf> },
+    /// A command line run as `<shell> -c <command>`
+    Shell { shell: PathBuf, command: String },
+    /// A program and its arguments, run without a shell
+    Exec(Vec<String>),
+}
+
+impl ScriptHook {
+    /// How the hook is referred to in logs and reports
+    pub fn name(&self) -> String {
+        match &self.command {
+            HookCommand::Script { path, .. } => path.display().to_string(),
+            HookCommand::Shell { command, .. } => command.clone(),
+            HookCommand::Exec(args) => args.join(" "),
+        }
+    }
+    
+    /// Short name tagging the hook's output: the script or program's file
+    /// name, or the command line
+    pub fn label(&self) -> String {
+        let file_name = |path: &Path| path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned();
+        match &self.command {
+            HookCommand::Script { path, .. } => file_name(path),
+            HookCommand::Shell { command, .. } => command.clone(),
+            HookCommand::Exec(args) => file_name(Path::new(&args[0])),
+        }
+    }
+    
+    /// Expands the hook's script, program, shell and working directory
+    /// paths, see [`expand_path`]. Program names without a `/` are left for
+    /// `PATH` lookup.
+    pub fn expand_paths(&mut self, base: &Path) -> Result<(), String> {
+        match self.command {
+            HookCommand::Script { ref mut path, ref mut shell } => {
+                *path = expand_path(path, base)?;
+                if let Some(shell) = shell {
+                    *shell = expand_program(shell, base)?;
+                }
+            }
+            HookCommand::Shell { ref mut shell, .. } => *shell = expand_program(shell, base)?,
+            HookCommand::Exec(ref mut args) => {
+                args[0] = expand_program(Path::new(&args[0]), base)?.to_string_lossy().into_owned();
+            }
+        }
+        if let Some(ref mut cwd) = self.cwd {
+            *cwd = expand_path(cwd, base)?;
+        }
+        Ok(())
+    }
+}
+
+impl ScriptHook {
+    /// A command line run by the default shell, e.g. from `--dark-script`
+    pub fn shell_command(command: impl Into<String>) -> Self {
+        Self {
+            command: HookCommand::Shell {
+                shell: PathBuf::from(DEFAULT_SHELL),
+                command: command.into(),
+            },
+            env: HashMap::new(),
+            cwd: None,
+            timeout: None,
+        }
+    }
+}
+
+impl From<PathBuf> for ScriptHook {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            command: HookCommand::Script { path, shell: None },
+            env: HashMap::new(),
+            cwd: None,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ScriptHookDefinition {
+    Path(PathBuf),
+    Table(ScriptHookTable),
+}
+
+/// The keys of [`ScriptHookTable`]. Its untagged definition hides unknown keys
+/// from `serde_ignored`, so [`unknown_keys`] looks for them itself.
+const SCRIPT_HOOK_KEYS: &[&str] = &["path", "command", "env", "cwd", "shell", "timeout"];
+
+#[derive(Deserialize, Serialize)]
+struct ScriptHookTable {
+    /// Script run directly, or through a shell when `shell` is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    command: Option<CommandLine>,
+    
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    env: HashMap<String, String>,
+    
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cwd: Option<PathBuf>,
+    
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    shell: Option<ShellChoice>,
+    
+    #[serde(default, with = "optional_duration_format", skip_serializing_if = "Option::is_none")]
+    timeout: Option<Duration>,
+}
+
+/// A command line for the shell, or a program and its arguments
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum CommandLine {
+    Line(String),
+    Args(Vec<String>),
+}
+
+/// `true` or `false` to use the default shell or none, or the shell to use
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ShellChoice {
+    Enabled(bool),
+    Program(PathBuf),
+}
+
+impl TryFrom<ScriptHookDefinition> for ScriptHook {
+    type Error = String;
+    
+    fn try_from(definition: ScriptHookDefinition) -> Result<Self, Self::Error> {
+        let table = match definition {
+            ScriptHookDefinition::Path(path) => return Ok(path.into()),
+            ScriptHookDefinition::Table(table) => table,
+        };
+        
+        let shell = match table.shell {
+            Some(ShellChoice::Enabled(true)) => Some(PathBuf::from(DEFAULT_SHELL)),
+            None | Some(ShellChoice::Enabled(false)) => None,
+            Some(ShellChoice::Program(ref shell)) => Some(shell.clone()),
+        };
+        
+        let command = match (table.path, table.command) {
+            (Some(_), Some(_)) => return Err("a script sets both `path` and `command`".to_string()),
+            (None, None) => return Err("a script table needs a `path` or a `command`".to_string()),
+            (Some(path), None) => HookCommand::Script { path, shell },
+            // A command line always needs a shell, `sh` unless another is given
+            (None, Some(CommandLine::Line(command))) => match table.shell {
+                Some(ShellChoice::Enabled(false)) => {
+                    return Err(format!(
+                        "command {:?} needs a shell; give it as a list to run it with `shell = false`",
+                        command
+                    ));
+                }
+                _ => HookCommand::Shell {
+                    shell: shell.unwrap_or_else(|| PathBuf::from(DEFAULT_SHELL)),
+                    command,
+                },
+            },
+            (None, Some(CommandLine::Args(args))) => {
+                if args.is_empty() {
+                    return Err("a script's `command` list is empty".to_string());
+                }
+                // A list is already split into arguments, so it runs without a shell
+                if table.shell.is_some_and(|shell| !matches!(shell, ShellChoice::Enabled(false))) {
+                    return Err(format!(
+                        "command {:?} is a list, which runs without a shell; give it as a string to use one",
+                        args.join(" ")
+                    ));
+                }
+                HookCommand::Exec(args)
+            }
+        };
+        
+        Ok(Self {
+            command,
+            env: table.env,
+            cwd: table.cwd,
+            timeout: table.timeout,
+        })
+    }
+}
+
+impl From<ScriptHook> for ScriptHookDefinition {
+    fn from(hook: ScriptHook) -> Self {
+        let shell_choice = |shell: PathBuf| match shell.as_os_str() == DEFAULT_SHELL {
+            true => ShellChoice::Enabled(true),
+            false => ShellChoice::Program(shell),
+        };
+        let (path, command, shell) = match hook.command {
+            HookCommand::Script { path, shell } => (Some(path), None, shell.map(shell_choice)),
+            // Command lines use `sh` by default, so only another shell is written
+            HookCommand::Shell { shell, command } => {
+                let shell = Some(shell_choice(shell)).filter(|shell| !matches!(shell, ShellChoice::Enabled(true)));
+                (None, Some(CommandLine::Line(command)), shell)
+            }
+            HookCommand::Exec(args) => (None, Some(CommandLine::Args(args)), None),
+        };
+        
+        ScriptHookDefinition::Table(ScriptHookTable {
+            path,
+            command,
+            env: hook.env,
+            cwd: hook.cwd,
+            shell,
+            timeout: hook.timeout,
+        })
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
 pub struct LuaScriptsConfig {
     #[serde(default)]
     pub light: Vec<PathBuf>,
@@ -49,53 +478,633 @@ pub struct LuaScriptsConfig {
     pub any: Vec<PathBuf>,
 }
 
-impl Default for Config {
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct FileConfig {
+    /// File containing `light` or `dark`, defaults to `~/.local/state/theme`
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+impl FileConfig {
+    pub fn path(&self) -> PathBuf {
+        self.path.clone().unwrap_or_else(crate::monitors::file::default_theme_file)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ScheduleConfig {
+    /// Time of day to switch to the light theme, as `HH:MM`
+    #[serde(default = "default_light_time", deserialize_with = "deserialize_time")]
+    pub light: NaiveTime,
+    
+    /// Time of day to switch to the dark theme, as `HH:MM`
+    #[serde(default = "default_dark_time", deserialize_with = "deserialize_time")]
+    pub dark: NaiveTime,
+    
+    /// Per-weekday overrides, e.g. `[schedule.days.saturday]`
+    #[serde(default)]
+    pub days: HashMap<Weekday, DayScheduleConfig>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct DayScheduleConfig {
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub light: Option<NaiveTime>,
+    
+    #[serde(default, deserialize_with = "deserialize_optional_time")]
+    pub dark: Option<NaiveTime>,
+}
+
+impl Default for ScheduleConfig {
     fn default() -> Self {
         Self {
-            general: GeneralConfig::default(),
-            scripts: ScriptsConfig::default(),
-            lua_scripts: LuaScriptsConfig::default(),
+            light: default_light_time(),
+            dark: default_dark_time(),
+            days: HashMap::new(),
         }
     }
 }
 
-impl Default for GeneralConfig {
+fn default_light_time() -> NaiveTime {
+    NaiveTime::from_hms_opt(7, 0, 0).unwrap()
+}
+
+fn default_dark_time() -> NaiveTime {
+    NaiveTime::from_hms_opt(19, 0, 0).unwrap()
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct SolarConfig {
+    /// Latitude in degrees, north positive
+    #[serde(default)]
+    pub latitude: Option<f64>,
+    
+    /// Longitude in degrees, east positive
+    #[serde(default)]
+    pub longitude: Option<f64>,
+    
+    /// Which sun position counts as sunrise and sunset
+    #[serde(default)]
+    pub twilight: Twilight,
+    
+    /// Shift of the switch to light, e.g. `"-15m"` for 15 minutes before sunrise
+    #[serde(default, with = "offset_format")]
+    pub sunrise_offset: TimeDelta,
+    
+    /// Shift of the switch to dark, e.g. `"30m"` for 30 minutes after sunset
+    #[serde(default, with = "offset_format")]
+    pub sunset_offset: TimeDelta,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Twilight {
+    /// Sunrise and sunset proper
+    #[default]
+    Official,
+    /// Sun 6° below the horizon
+    Civil,
+    /// Sun 12° below the horizon
+    Nautical,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct AmbientConfig {
+    /// Directory containing the IIO devices
+    #[serde(default = "default_iio_root")]
+    pub sysfs_root: PathBuf,
+    
+    /// Device directory below `sysfs_root`, e.g. `iio:device0`; the first
+    /// device reporting illuminance is used when unset
+    #[serde(default)]
+    pub device: Option<String>,
+    
+    /// Switch to dark when the illuminance drops below this many lux
+    #[serde(default = "default_dark_below")]
+    pub dark_below: f64,
+    
+    /// Switch to light when the illuminance rises above this many lux
+    #[serde(default = "default_light_above")]
+    pub light_above: f64,
+    
+    /// How often to sample the sensor
+    #[serde(default = "default_ambient_interval", with = "duration_format")]
+    pub interval: Duration,
+    
+    /// How long a reading must stay past a threshold before switching
+    #[serde(default = "default_ambient_dwell", with = "duration_format")]
+    pub dwell: Duration,
+}
+
+impl Default for AmbientConfig {
     fn default() -> Self {
         Self {
-            quiet: false,
-            log_file: None,
-            ipc: false,
+            sysfs_root: default_iio_root(),
+            device: None,
+            dark_below: default_dark_below(),
+            light_above: default_light_above(),
+            interval: default_ambient_interval(),
+            dwell: default_ambient_dwell(),
         }
     }
 }
 
-impl Default for ScriptsConfig {
+fn default_iio_root() -> PathBuf {
+    PathBuf::from("/sys/bus/iio/devices")
+}
+
+fn default_dark_below() -> f64 {
+    20.0
+}
+
+fn default_light_above() -> f64 {
+    80.0
+}
+
+fn default_ambient_interval() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_ambient_dwell() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CommandConfig {
+    /// Shell command printing or signalling the current theme
+    #[serde(default)]
+    pub probe: Option<String>,
+    
+    /// How often to run the probe; `"0s"` only runs it on triggers
+    #[serde(default = "default_probe_interval", with = "duration_format")]
+    pub interval: Duration,
+    
+    /// How long the probe may run before its process group is terminated
+    #[serde(default = "default_probe_timeout", with = "duration_format")]
+    pub timeout: Duration,
+    
+    /// Long-running shell command; every line it prints re-runs the probe
+    #[serde(default)]
+    pub trigger: Option<String>,
+    
+    /// Rules mapping the probe's result to a theme, first match wins.
+    /// Without rules the probe must print `light` or `dark`.
+    #[serde(default)]
+    pub rules: Vec<CommandRule>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CommandRule {
+    /// Theme to use when all conditions of the rule hold
+    pub theme: Theme,
+    
+    /// Exit code the probe must return
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    
+    /// Exact, trimmed stdout the probe must print
+    #[serde(default)]
+    pub stdout: Option<String>,
+    
+    /// Case-insensitive substring the probe's stdout must contain
+    #[serde(default)]
+    pub stdout_contains: Option<String>,
+}
+
+impl Default for CommandConfig {
     fn default() -> Self {
         Self {
-            light: Vec::new(),
-            dark: Vec::new(),
-            any: Vec::new(),
+            probe: None,
+            interval: default_probe_interval(),
+            timeout: default_probe_timeout(),
+            trigger: None,
+            rules: Vec::new(),
         }
     }
 }
 
-impl Default for LuaScriptsConfig {
-    fn default() -> Self {
-        Self {
-            light: Vec::new(),
-            dark: Vec::new(),
-            any: Vec::new(),
+fn default_probe_interval() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_probe_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone)]
+pub struct CompositeConfig {
+    /// How the sources' themes are combined
+    #[serde(default)]
+    pub policy: ArbitrationPolicy,
+    
+    #[serde(default)]
+    pub sources: Vec<CompositeSourceConfig>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArbitrationPolicy {
+    /// The highest-priority source with an opinion wins
+    #[default]
+    Priority,
+    /// The source that reported most recently wins
+    Latest,
+    /// The theme only changes once all sources agree
+    Unanimous,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CompositeSourceConfig {
+    /// Backend name or fallback list, as in `[general] monitor`
+    #[serde(deserialize_with = "deserialize_string_or_list")]
+    pub monitor: Vec<String>,
+    
+    /// Higher priorities win under the `priority` policy
+    #[serde(default)]
+    pub priority: i32,
+    
+    /// Only let this source vote for one theme, e.g. `"dark"` to force dark
+    /// while it reports dark and defer to other sources otherwise
+    #[serde(default)]
+    pub only: Option<Theme>,
+}
+
+/// Expands a leading `~` and `$VAR` or `${VAR}` references in `path`, then
+/// resolves it against `base` if it is relative
+pub fn expand_path(path: &Path, base: &Path) -> Result<PathBuf, String> {
+    // Paths that aren't valid UTF-8 can't contain anything to expand
+    let Some(value) = path.to_str() else {
+        return Ok(base.join(path));
+    };
+    
+    let mut expanded = String::new();
+    let mut rest = value;
+    if let Some(after) = rest.strip_prefix('~') {
+        if !after.is_empty() && !after.starts_with('/') {
+            return Err(format!("cannot expand {:?}: only ~ for the current user is supported", value));
+        }
+        let home = dirs::home_dir().ok_or_else(|| format!("cannot expand {:?}: no home directory", value))?;
+        expanded.push_str(&home.to_string_lossy());
+        rest = after;
+    }
+    
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        
+        let (name, after) = match rest.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| format!("cannot expand {:?}: unclosed ${{", value))?;
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = rest
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            }
+        };
+        
+        // A `$` not followed by a name is kept as is
+        if name.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+        let variable = std::env::var(name)
+            .map_err(|_| format!("cannot expand {:?}: environment variable {} is not set", value, name))?;
+        expanded.push_str(&variable);
+        rest = after;
+    }
+    expanded.push_str(rest);
+    
+    Ok(base.join(expanded))
+}
+
+/// Like [`expand_path`], but leaves bare program names such as `bash` to be
+/// looked up in `PATH`
+fn expand_program(program: &Path, base: &Path) -> Result<PathBuf, String> {
+    let value = program.to_string_lossy();
+    if value.starts_with('~') || value.contains('/') || value.contains('$') {
+        expand_path(program, base)
+    } else {
+        Ok(program.to_path_buf())
+    }
+}
+
+/// Dotted paths of the keys in `content` that no config setting reads, e.g.
+/// `general.quite`
+pub fn unknown_keys(content: &str) -> Result<Vec<String>, toml::de::Error> {
+    let mut unknown = Vec::new();
+    let _: Config = serde_ignored::deserialize(toml::Deserializer::new(content), |path| {
+        unknown.push(path.to_string());
+    })?;
+    
+    let table: toml::Table = toml::from_str(content)?;
+    let scripts = table.get("scripts").and_then(toml::Value::as_table);
+    for theme in ["light", "dark", "any"] {
+        let Some(entries) = scripts.and_then(|scripts| scripts.get(theme)).and_then(toml::Value::as_array) else {
+            continue;
+        };
+        for (index, entry) in entries.iter().enumerate() {
+            let path = format!("scripts.{}.{}", theme, index);
+            let hooks: Vec<(String, &toml::Value)> = match entry {
+                toml::Value::Array(sequence) => sequence
+                    .iter()
+                    .enumerate()
+                    .map(|(step, hook)| (format!("{}.{}", path, step), hook))
+                    .collect(),
+                hook => vec![(path, hook)],
+            };
+            for (path, hook) in hooks {
+                let Some(hook) = hook.as_table() else { continue };
+                for key in hook.keys().filter(|key| !SCRIPT_HOOK_KEYS.contains(&key.as_str())) {
+                    unknown.push(format!("{}.{}", path, key));
+                }
+            }
+        }
+    }
+    Ok(unknown)
+}
+
+/// Where the config is looked for without `--config`, in order:
+/// `$XDG_CONFIG_HOME/theme-switcher/config.toml`, then
+/// `~/.config/theme-switcher/config.toml`
+pub fn default_config_paths() -> Vec<PathBuf> {
+    config_paths(std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from), dirs::home_dir())
+}
+
+fn config_paths(config_home: Option<PathBuf>, home: Option<PathBuf>) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    // The spec says to ignore relative values
+    if let Some(config_home) = config_home.filter(|dir| dir.is_absolute()) {
+        paths.push(config_home.join("theme-switcher/config.toml"));
+    }
+    if let Some(home) = home {
+        let path = home.join(".config/theme-switcher/config.toml");
+        if !paths.contains(&path) {
+            paths.push(path);
         }
     }
+    paths
+}
+
+/// The source line `line` of `content`, with a caret under `column`
+fn snippet(content: &str, line: usize, column: usize) -> String {
+    let text = content.lines().nth(line - 1).unwrap_or_default();
+    let number = line.to_string();
+    let gutter = " ".repeat(number.len());
+    format!("{gutter} |\n{number} | {text}\n{gutter} | {}^", " ".repeat(column - 1))
+}
+
+/// The 1-based line and column of a byte offset in `content`
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Parses a time of day as `HH:MM` or `HH:MM:SS`
+pub fn parse_time(value: &str) -> Result<NaiveTime, String> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M:%S"))
+        .map_err(|_| format!("invalid time {:?}, expected HH:MM", value))
+}
+
+fn deserialize_time<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    parse_time(&value).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_optional_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<NaiveTime>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_time(&value).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// Parses a duration made of `<number><unit>` parts, e.g. `500ms`, `30s`,
+/// `5m` or `1h30m`
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration {:?}, expected e.g. \"30s\" or \"1h30m\"", value);
+    let mut rest = value.trim();
+    if rest.is_empty() {
+        return Err(invalid());
+    }
+    
+    let mut total = Duration::ZERO;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+        let amount: u64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        
+        let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+        let too_large = || format!("duration {:?} is too large", value);
+        let part = match &rest[..unit_len] {
+            "ms" => Duration::from_millis(amount),
+            "s" => Duration::from_secs(amount),
+            "m" => Duration::from_secs(amount.checked_mul(60).ok_or_else(too_large)?),
+            "h" => Duration::from_secs(amount.checked_mul(3600).ok_or_else(too_large)?),
+            _ => return Err(invalid()),
+        };
+        total = total.checked_add(part).ok_or_else(too_large)?;
+        rest = &rest[unit_len..];
+    }
+    
+    Ok(total)
+}
+
+/// Parses a signed offset, e.g. `30m` or `-15m`
+pub fn parse_offset(value: &str) -> Result<TimeDelta, String> {
+    let value = value.trim();
+    let (negative, magnitude) = match value.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    
+    let delta = TimeDelta::from_std(parse_duration(magnitude)?)
+        .map_err(|_| format!("offset {:?} is too large", value))?;
+    Ok(if negative { -delta } else { delta })
+}
+
+/// Formats a duration the way `parse_duration` reads it, e.g. `30s` or `500ms`
+pub fn format_duration(duration: Duration) -> String {
+    if duration.subsec_nanos() == 0 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}ms", duration.as_millis())
+    }
+}
+
+mod duration_format {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+    
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::format_duration(*duration))
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        super::parse_duration(&value).map_err(serde::de::Error::custom)
+    }
+}
+
+mod optional_duration_format {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+    
+    pub fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(duration) => super::duration_format::serialize(duration, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| super::parse_duration(&value).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+mod offset_format {
+    use chrono::TimeDelta;
+    use serde::{Deserialize, Deserializer, Serializer};
+    
+    pub fn serialize<S: Serializer>(offset: &TimeDelta, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{}s", offset.num_seconds()))
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<TimeDelta, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        super::parse_offset(&value).map_err(serde::de::Error::custom)
+    }
 }
 
 impl Config {
-    pub fn load_from_file(path: &PathBuf) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = std::fs::read_to_string(path)?;
-        let config: Config = toml::from_str(&content)?;
+    /// Loads the config, expanding its paths relative to the file's
+    /// directory. Unknown keys are an error if `strict` is set, and only
+    /// logged otherwise.
+    pub fn load_from_file(path: &PathBuf, strict: bool) -> ThemeResult<Self> {
+        let error = |message: String| ThemeSwitcherError::Config {
+            file: Some(path.clone()),
+            line: None,
+            column: None,
+            message,
+            snippet: None,
+        };
+        
+        let content = std::fs::read_to_string(path).map_err(|e| error(e.to_string()))?;
+        let mut config: Config = toml::from_str(&content).map_err(|e| match e.span() {
+            Some(span) => {
+                let (line, column) = line_column(&content, span.start);
+                ThemeSwitcherError::Config {
+                    file: Some(path.clone()),
+                    line: Some(line),
+                    column: Some(column),
+                    message: e.message().to_string(),
+                    snippet: Some(snippet(&content, line, column)),
+                }
+            }
+            None => error(e.message().to_string()),
+        })?;
+        
+        // The content parsed, so looking for unknown keys can't fail
+        let unknown = unknown_keys(&content).unwrap_or_default();
+        if !unknown.is_empty() {
+            let message = format!("unknown keys: {}", unknown.join(", "));
+            if strict {
+                return Err(error(message));
+            }
+            log_warn(&format!("Config {}: {}", path.display(), message));
+        }
+        
+        let base = std::path::absolute(path)
+            .map_err(|e| error(e.to_string()))?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        config.expand_paths(&base).map_err(|e| error(format!("invalid path: {}", e)))?;
+        config.loaded_from = Some(path.clone());
         Ok(config)
     }
     
+    /// Loads `path`, or else the first default config file that exists, see
+    /// [`default_config_paths`]. With `strict`, unknown keys are an error
+    /// and an invalid default config stops the load; otherwise they are
+    /// logged and the invalid default config is skipped.
+    pub fn load(path: Option<&PathBuf>, strict: bool) -> ThemeResult<Option<Self>> {
+        if let Some(path) = path {
+            log_info(&format!("Loading config from: {:?}", path));
+            return Self::load_from_file(path, strict).map(Some);
+        }
+        
+        let Some(path) = default_config_paths().into_iter().find(|path| path.exists()) else {
+            return Ok(None);
+        };
+        log_info(&format!("Loading config from: {:?}", path));
+        match Self::load_from_file(&path, strict) {
+            Ok(config) => Ok(Some(config)),
+            Err(e) if !strict => {
+                log_warn(&format!("{}\nContinuing without a config file (use --strict to stop instead)", e));
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// Expands every path in the config with [`expand_path`]
+    pub fn expand_paths(&mut self, base: &Path) -> Result<(), String> {
+        let expand = |key: &str, path: &mut PathBuf| -> Result<(), String> {
+            *path = expand_path(path, base).map_err(|e| format!("{}: {}", key, e))?;
+            Ok(())
+        };
+        
+        if let Some(ref mut log_file) = self.general.log_file {
+            expand("general.log_file", log_file)?;
+        }
+        if let Some(ref mut state_file) = self.general.state_file {
+            expand("general.state_file", state_file)?;
+        }
+        if let Some(ref mut path) = self.file.path {
+            expand("file.path", path)?;
+        }
+        expand("ambient.sysfs_root", &mut self.ambient.sysfs_root)?;
+        
+        let scripts = [
+            ("scripts.light", &mut self.scripts.light),
+            ("scripts.dark", &mut self.scripts.dark),
+            ("scripts.any", &mut self.scripts.any),
+        ];
+        for (key, entries) in scripts {
+            for (index, entry) in entries.iter_mut().enumerate() {
+                let hooks = match entry {
+                    ScriptEntry::Script(hook) => std::slice::from_mut(hook),
+                    ScriptEntry::Sequence(hooks) => hooks,
+                };
+                for hook in hooks {
+                    hook.expand_paths(base).map_err(|e| format!("{}[{}]: {}", key, index, e))?;
+                }
+            }
+        }
+        
+        let lua_scripts = [
+            ("lua_scripts.light", &mut self.lua_scripts.light),
+            ("lua_scripts.dark", &mut self.lua_scripts.dark),
+            ("lua_scripts.any", &mut self.lua_scripts.any),
+        ];
+        for (key, paths) in lua_scripts {
+            for (index, path) in paths.iter_mut().enumerate() {
+                expand(&format!("{}[{}]", key, index), path)?;
+            }
+        }
+        Ok(())
+    }
+    
     pub fn has_scripts(&self) -> bool {
         !self.scripts.light.is_empty() || 
         !self.scripts.dark.is_empty() || 
@@ -107,4 +1116,256 @@ impl Config {
         !self.lua_scripts.dark.is_empty() || 
         !self.lua_scripts.any.is_empty()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn light_scripts(toml: &str) -> Result<Vec<ScriptEntry>, String> {
+        toml::from_str::<ScriptsConfig>(&format!("light = {}", toml))
+            .map(|scripts| scripts.light)
+            .map_err(|e| e.message().to_string())
+    }
+    
+    fn hook(toml: &str) -> ScriptHook {
+        match light_scripts(&format!("[{}]", toml)).unwrap().remove(0) {
+            ScriptEntry::Script(hook) => hook,
+            entry => panic!("expected a single script, got {:?}", entry),
+        }
+    }
+    
+    fn error(toml: &str) -> String {
+        light_scripts(&format!("[{}]", toml)).unwrap_err()
+    }
+    
+    fn script(path: &str, shell: Option<&str>) -> HookCommand {
+        HookCommand::Script {
+            path: PathBuf::from(path),
+            shell: shell.map(PathBuf::from),
+        }
+    }
+    
+    #[test]
+    fn plain_path_runs_directly() {
+        assert_eq!(hook(r#""/home/me/My Scripts/dark.sh""#).command, script("/home/me/My Scripts/dark.sh", None));
+        assert_eq!(hook(r#"{ path = "dark.sh" }"#).command, script("dark.sh", None));
+        assert_eq!(hook(r#"{ path = "dark.sh", shell = false }"#).command, script("dark.sh", None));
+    }
+    
+    #[test]
+    fn path_with_shell() {
+        assert_eq!(hook(r#"{ path = "dark.sh", shell = true }"#).command, script("dark.sh", Some("sh")));
+        assert_eq!(hook(r#"{ path = "dark.sh", shell = "bash" }"#).command, script("dark.sh", Some("bash")));
+    }
+    
+    #[test]
+    fn command_line_runs_in_shell() {
+        let shell = |shell: &str| HookCommand::Shell {
+            shell: PathBuf::from(shell),
+            command: "echo $THEME_SWITCHER_THEME".to_string(),
+        };
+        assert_eq!(hook(r#"{ command = "echo $THEME_SWITCHER_THEME" }"#).command, shell("sh"));
+        assert_eq!(hook(r#"{ command = "echo $THEME_SWITCHER_THEME", shell = true }"#).command, shell("sh"));
+        assert_eq!(hook(r#"{ command = "echo $THEME_SWITCHER_THEME", shell = "bash" }"#).command, shell("bash"));
+    }
+    
+    #[test]
+    fn command_list_runs_without_shell() {
+        assert_eq!(
+            hook(r#"{ command = ["kitty", "@", "set-colors", "dark.conf"] }"#).command,
+            HookCommand::Exec(vec!["kitty".into(), "@".into(), "set-colors".into(), "dark.conf".into()])
+        );
+    }
+    
+    #[test]
+    fn table_options() {
+        let hook = hook(r#"{ path = "dark.sh", env = { LOG = "1" }, cwd = "/tmp", timeout = "5s" }"#);
+        assert_eq!(hook.env, HashMap::from([("LOG".to_string(), "1".to_string())]));
+        assert_eq!(hook.cwd, Some(PathBuf::from("/tmp")));
+        assert_eq!(hook.timeout, Some(Duration::from_secs(5)));
+    }
+    
+    #[test]
+    fn nested_list_is_a_sequence() {
+        let entries = light_scripts(r#"["first.sh", ["second.sh", { command = "echo third" }]]"#).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].scripts().len(), 1);
+        let names: Vec<String> = entries[1].scripts().iter().map(ScriptHook::name).collect();
+        assert_eq!(names, ["second.sh", "echo third"]);
+    }
+    
+    #[test]
+    fn list_of_two_paths_is_a_sequence() {
+        let entries = light_scripts(r#"[["first.sh", "second.sh"]]"#).unwrap();
+        let names: Vec<String> = entries[0].scripts().iter().map(ScriptHook::name).collect();
+        assert_eq!(names, ["first.sh", "second.sh"]);
+    }
+    
+    #[test]
+    fn invalid_tables_are_rejected() {
+        assert!(error(r#"{ path = "a.sh", command = "b" }"#).contains("both `path` and `command`"));
+        assert!(error(r#"{ cwd = "/tmp" }"#).contains("needs a `path` or a `command`"));
+        assert!(error(r#"{ command = "echo hi", shell = false }"#).contains("needs a shell"));
+        assert!(error(r#"{ command = [] }"#).contains("list is empty"));
+        assert!(error(r#"{ command = ["echo", "hi"], shell = "bash" }"#).contains("runs without a shell"));
+    }
+    
+    #[test]
+    fn hooks_survive_serialization() {
+        let toml = r#"light = [
+            "plain.sh",
+            { path = "shell.sh", shell = true },
+            { path = "bash.sh", shell = "bash", timeout = "1m" },
+            { command = "echo hi" },
+            { command = "echo hi", shell = "zsh", env = { A = "b" } },
+            { command = ["kitty", "@"], cwd = "/tmp" },
+            ["first.sh", "second.sh"],
+        ]"#;
+        let scripts: ScriptsConfig = toml::from_str(toml).unwrap();
+        let reparsed: ScriptsConfig = toml::from_str(&toml::to_string(&scripts).unwrap()).unwrap();
+        assert_eq!(reparsed.light, scripts.light);
+    }
+    
+    #[test]
+    fn valid_config_has_no_unknown_keys() {
+        let content = r#"
+            [general]
+            quiet = true
+            
+            [scripts]
+            light = ["light.sh", { command = "echo hi", timeout = "5s" }]
+        "#;
+        assert!(unknown_keys(content).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn reports_unknown_keys() {
+        let content = r#"
+            [general]
+            quite = true
+            
+            [scripts]
+            light = [{ path = "light.sh", timout = "5s" }]
+            dark = [["first.sh", { command = "echo hi", evn = { A = "b" } }]]
+        "#;
+        let mut unknown = unknown_keys(content).unwrap();
+        unknown.sort();
+        assert_eq!(unknown, ["general.quite", "scripts.dark.0.1.evn", "scripts.light.0.timout"]);
+    }
+    
+    #[test]
+    fn expands_home_and_variables() {
+        let home = dirs::home_dir().unwrap();
+        let base = Path::new("/etc/theme-switcher");
+        let expand = |path: &str| expand_path(Path::new(path), base);
+        
+        assert_eq!(expand("~").unwrap(), home);
+        assert_eq!(expand("~/x").unwrap(), home.join("x"));
+        assert_eq!(expand("$HOME/x").unwrap(), home.join("x"));
+        assert_eq!(expand("${HOME}/x").unwrap(), home.join("x"));
+        assert_eq!(expand("/opt/a$/b").unwrap(), PathBuf::from("/opt/a$/b"));
+    }
+    
+    #[test]
+    fn resolves_relative_paths_against_the_config_directory() {
+        let base = Path::new("/etc/theme-switcher");
+        assert_eq!(expand_path(Path::new("scripts/dark.sh"), base).unwrap(), base.join("scripts/dark.sh"));
+        assert_eq!(expand_path(Path::new("/usr/bin/dark"), base).unwrap(), PathBuf::from("/usr/bin/dark"));
+        assert_eq!(expand_program(Path::new("bash"), base).unwrap(), PathBuf::from("bash"));
+        assert_eq!(expand_program(Path::new("bin/sh"), base).unwrap(), base.join("bin/sh"));
+    }
+    
+    #[test]
+    fn rejects_paths_that_cannot_be_expanded() {
+        let expand = |path: &str| expand_path(Path::new(path), Path::new("/")).unwrap_err();
+        assert!(expand("$THEME_SWITCHER_TEST_UNSET/x").contains("THEME_SWITCHER_TEST_UNSET is not set"));
+        assert!(expand("${THEME_SWITCHER_TEST_UNSET}").contains("is not set"));
+        assert!(expand("~root/x").contains("only ~ for the current user"));
+        assert!(expand("${HOME/x").contains("unclosed ${"));
+    }
+    
+    /// Writes `content` to a config file for one test
+    fn config_file(test: &str, content: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("theme-switcher-config-{}-{}", test, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+    
+    #[test]
+    fn finds_lines_and_columns() {
+        let content = "[general]\nquiet = true\nlog_level = 3";
+        assert_eq!(line_column(content, 0), (1, 1));
+        assert_eq!(line_column(content, content.find("quiet").unwrap()), (2, 1));
+        assert_eq!(line_column(content, content.find('3').unwrap()), (3, 13));
+        assert_eq!(line_column(content, content.len()), (3, 14));
+        
+        let crlf = "[general]\r\nquiet = true\r\nlog_level = 3\r\n";
+        assert_eq!(line_column(crlf, crlf.find('3').unwrap()), (3, 13));
+    }
+    
+    #[test]
+    fn snippet_marks_the_column() {
+        assert_eq!(snippet("a = 1\nquiet = tru\n", 2, 9), "  |\n2 | quiet = tru\n  |         ^");
+    }
+    
+    #[test]
+    fn reports_the_position_of_syntax_errors() {
+        for (test, newline) in [("lf", "\n"), ("crlf", "\r\n")] {
+            let path = config_file(test, &["[general]", "quiet = tru", ""].join(newline));
+            let Err(ThemeSwitcherError::Config { line, column, snippet, .. }) = Config::load_from_file(&path, false) else {
+                panic!("expected a config error");
+            };
+            assert_eq!((line, column), (Some(2), Some(9)), "{}", test);
+            assert_eq!(snippet.unwrap(), "  |\n2 | quiet = tru\n  |         ^", "{}", test);
+            std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        }
+    }
+    
+    #[test]
+    fn strict_rejects_unknown_keys() {
+        let path = config_file("strict", "[general]\nquite = true\n");
+        assert!(Config::load_from_file(&path, false).is_ok());
+        let Err(ThemeSwitcherError::Config { message, .. }) = Config::load(Some(&path), true) else {
+            panic!("expected a config error");
+        };
+        assert_eq!(message, "unknown keys: general.quite");
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+    
+    #[test]
+    fn looks_in_xdg_config_home_first() {
+        let home = PathBuf::from("/home/me");
+        assert_eq!(
+            config_paths(Some(PathBuf::from("/xdg")), Some(home.clone())),
+            [PathBuf::from("/xdg/theme-switcher/config.toml"), home.join(".config/theme-switcher/config.toml")]
+        );
+        assert_eq!(
+            config_paths(Some(home.join(".config")), Some(home.clone())),
+            [home.join(".config/theme-switcher/config.toml")]
+        );
+        assert_eq!(
+            config_paths(Some(PathBuf::from("relative")), Some(home.clone())),
+            [home.join(".config/theme-switcher/config.toml")]
+        );
+    }
+    
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms").unwrap(), Duration::from_millis(500));
+        assert_eq!(parse_duration("1h30m").unwrap(), Duration::from_secs(5400));
+        assert!(parse_duration("5x").unwrap_err().starts_with("invalid duration"));
+        assert!(parse_duration("").unwrap_err().starts_with("invalid duration"));
+    }
+    
+    #[test]
+    fn rejects_durations_that_overflow() {
+        let too_large = |value: &str| parse_duration(value).unwrap_err().ends_with("is too large");
+        assert!(too_large(&format!("{}m", u64::MAX / 60 + 1)));
+        assert!(too_large(&format!("{}h", u64::MAX / 3600 + 1)));
+        assert!(too_large(&format!("{}s1s", u64::MAX)));
+        assert!(parse_duration(&format!("{}s", u64::MAX)).is_ok());
+    }
+}
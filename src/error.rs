@@ -1,15 +1,85 @@
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 pub enum ThemeSwitcherError {
+    /// The configuration is invalid, at a position in its file if known
+    Config {
+        file: Option<PathBuf>,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+        /// The offending source line, marked at the column
+        snippet: Option<String>,
+    },
+    /// A shell script hook failed or could not be run
+    Script(String),
+    /// A Lua hook or the Lua runtime failed
+    Lua(String),
+    /// The IPC socket could not be set up
+    Ipc(String),
+    /// None of the preferred theme backends works here
+    BackendUnavailable(String),
+    /// Another daemon is already listening on the IPC socket
+    AlreadyRunning(PathBuf),
     MonitorError(String),
     PlatformError(String),
 }
 
+impl ThemeSwitcherError {
+    /// A config error without a known file position
+    pub fn config(message: impl Into<String>) -> Self {
+        ThemeSwitcherError::Config {
+            file: None,
+            line: None,
+            column: None,
+            message: message.into(),
+            snippet: None,
+        }
+    }
+
+    /// The process exit code for this error; see "Exit Codes" in the README
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ThemeSwitcherError::MonitorError(_) | ThemeSwitcherError::PlatformError(_) => 1,
+            ThemeSwitcherError::Config { .. } => 3,
+            ThemeSwitcherError::BackendUnavailable(_) => 4,
+            ThemeSwitcherError::AlreadyRunning(_) => 5,
+            ThemeSwitcherError::Ipc(_) => 6,
+            ThemeSwitcherError::Script(_) => 7,
+            ThemeSwitcherError::Lua(_) => 8,
+        }
+    }
+}
+
 impl fmt::Display for ThemeSwitcherError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            ThemeSwitcherError::Config { file, line, column, message, snippet } => {
+                write!(f, "Config error")?;
+                if let Some(file) = file {
+                    write!(f, " in {}", file.display())?;
+                    if let Some(line) = line {
+                        write!(f, ":{}", line)?;
+                        if let Some(column) = column {
+                            write!(f, ":{}", column)?;
+                        }
+                    }
+                }
+                write!(f, ": {}", message)?;
+                if let Some(snippet) = snippet {
+                    write!(f, "\n{}", snippet)?;
+                }
+                Ok(())
+            }
+            ThemeSwitcherError::Script(msg) => write!(f, "Script error: {}", msg),
+            ThemeSwitcherError::Lua(msg) => write!(f, "Lua error: {}", msg),
+            ThemeSwitcherError::Ipc(msg) => write!(f, "IPC error: {}", msg),
+            ThemeSwitcherError::BackendUnavailable(msg) => write!(f, "Backend unavailable: {}", msg),
+            ThemeSwitcherError::AlreadyRunning(socket) => {
+                write!(f, "Already running: another instance is listening on {}", socket.display())
+            }
             ThemeSwitcherError::MonitorError(msg) => write!(f, "Monitor error: {}", msg),
             ThemeSwitcherError::PlatformError(msg) => write!(f, "Platform error: {}", msg),
         }
@@ -20,7 +90,17 @@ impl Error for ThemeSwitcherError {}
 
 impl From<Box<dyn Error>> for ThemeSwitcherError {
     fn from(err: Box<dyn Error>) -> Self {
-        ThemeSwitcherError::MonitorError(err.to_string())
+        // Keep the variant of typed errors that were boxed on the way
+        match err.downcast::<ThemeSwitcherError>() {
+            Ok(err) => *err,
+            Err(err) => ThemeSwitcherError::MonitorError(err.to_string()),
+        }
+    }
+}
+
+impl From<mlua::Error> for ThemeSwitcherError {
+    fn from(err: mlua::Error) -> Self {
+        ThemeSwitcherError::Lua(err.to_string())
     }
 }
 
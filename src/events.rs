@@ -0,0 +1,224 @@
+use crate::{Theme, logging};
+use chrono::{DateTime, Local};
+use futures_util::{Stream, StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+/// What triggered a theme change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThemeSource {
+    /// The desktop's appearance setting
+    System,
+    /// A fixed or solar schedule
+    Schedule,
+    /// The ambient light sensor
+    Sensor,
+    /// A probe command
+    Command,
+    /// A manual override through the theme file
+    Manual,
+    /// A client of the IPC socket
+    Ipc,
+    /// The daemon applying the current theme at startup
+    Startup,
+}
+
+impl fmt::Display for ThemeSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ThemeSource::System => "system",
+            ThemeSource::Schedule => "schedule",
+            ThemeSource::Sensor => "sensor",
+            ThemeSource::Command => "command",
+            ThemeSource::Manual => "manual",
+            ThemeSource::Ipc => "ipc",
+            ThemeSource::Startup => "startup",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A theme change as seen by handlers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ThemeEvent {
+    pub theme: Theme,
+    /// The theme before this change, if known
+    pub previous: Option<Theme>,
+    pub source: ThemeSource,
+    pub timestamp: DateTime<Local>,
+}
+
+impl ThemeEvent {
+    pub fn new(theme: Theme, source: ThemeSource) -> Self {
+        Self {
+            theme,
+            previous: None,
+            source,
+            timestamp: Local::now(),
+        }
+    }
+}
+
+/// Where monitors deliver their events. Cheap to clone and callable from any
+/// thread.
+#[derive(Clone)]
+pub struct EventSender {
+    sink: Arc<dyn Fn(ThemeEvent) + Send + Sync>,
+}
+
+impl EventSender {
+    pub fn new<F>(sink: F) -> Self
+    where
+        F: Fn(ThemeEvent) + Send + Sync + 'static,
+    {
+        Self { sink: Arc::new(sink) }
+    }
+
+    pub fn send(&self, event: ThemeEvent) {
+        (self.sink)(event);
+    }
+
+    /// Adapts callback-style monitors: every theme passed to the returned
+    /// callback is sent as an event from `source`
+    pub fn callback(&self, source: ThemeSource) -> impl Fn(Theme) + Send + Sync + 'static {
+        let sender = self.clone();
+        move |theme| sender.send(ThemeEvent::new(theme, source))
+    }
+}
+
+/// Stream of the events sent through a [`channel`].
+///
+/// Fills in each event's `previous` theme, and ends once every sender has
+/// been dropped.
+pub struct ThemeEvents {
+    receiver: mpsc::UnboundedReceiver<ThemeEvent>,
+    current: Option<Theme>,
+}
+
+impl Stream for ThemeEvents {
+    type Item = ThemeEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.receiver.poll_recv(cx);
+        if let Poll::Ready(Some(mut event)) = poll {
+            event.previous = event.previous.or(self.current);
+            self.current = Some(event.theme);
+            return Poll::Ready(Some(event));
+        }
+        poll
+    }
+}
+
+/// Creates a sender and the stream yielding what it sends. Sending never
+/// blocks.
+pub fn channel() -> (EventSender, ThemeEvents) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    let sender = EventSender::new(move |event| {
+        // The receiver is gone once the daemon shuts down
+        let _ = sender.send(event);
+    });
+    (sender, ThemeEvents { receiver, current: None })
+}
+
+/// Filters `events` before they reach the handlers.
+///
+/// Events for the theme that was last let through (initially `current`) are
+/// dropped. With a non-zero `debounce`, an event is held until no other event
+/// arrives for that long, so a burst of flips only yields its final state.
+/// Each event's `previous` is the theme last let through.
+pub fn filter<S>(events: S, current: Option<Theme>, debounce: Duration) -> impl Stream<Item = ThemeEvent>
+where
+    S: Stream<Item = ThemeEvent> + Unpin,
+{
+    stream::unfold((events, current), move |(mut events, mut current)| async move {
+        loop {
+            let mut event = events.next().await?;
+
+            if !debounce.is_zero() {
+                while let Ok(Some(next)) = tokio::time::timeout(debounce, events.next()).await {
+                    logging::log_trace(&format!("Debouncing {} theme from {}", event.theme, event.source));
+                    event = next;
+                }
+            }
+
+            if current == Some(event.theme) {
+                logging::log_trace(&format!("Ignoring {} theme from {}: already current", event.theme, event.source));
+                continue;
+            }
+
+            event.previous = current.or(event.previous);
+            current = Some(event.theme);
+            return Some((event, (events, current)));
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(theme: Theme) -> ThemeEvent {
+        ThemeEvent::new(theme, ThemeSource::Manual)
+    }
+
+    /// The theme and previous theme of each event `filter` lets through
+    async fn filtered<S>(events: S, current: Option<Theme>, debounce: Duration) -> Vec<(Theme, Option<Theme>)>
+    where
+        S: Stream<Item = ThemeEvent> + Unpin,
+    {
+        filter(events, current, debounce)
+            .map(|event| (event.theme, event.previous))
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn drops_events_for_the_current_theme() {
+        let events = stream::iter([Theme::Dark, Theme::Dark, Theme::Light, Theme::Light, Theme::Dark].map(event));
+        assert_eq!(
+            filtered(events, Some(Theme::Dark), Duration::ZERO).await,
+            [(Theme::Light, Some(Theme::Dark)), (Theme::Dark, Some(Theme::Light))]
+        );
+    }
+
+    #[tokio::test]
+    async fn lets_first_event_through_without_current_theme() {
+        let events = stream::iter([Theme::Light, Theme::Light].map(event));
+        assert_eq!(filtered(events, None, Duration::ZERO).await, [(Theme::Light, None)]);
+    }
+
+    #[tokio::test]
+    async fn debounce_keeps_final_theme_of_a_burst() {
+        let events = stream::iter([Theme::Light, Theme::Dark, Theme::Light].map(event));
+        assert_eq!(
+            filtered(events, Some(Theme::Dark), Duration::from_millis(50)).await,
+            [(Theme::Light, Some(Theme::Dark))]
+        );
+    }
+
+    #[tokio::test]
+    async fn debounce_drops_burst_ending_on_current_theme() {
+        let events = stream::iter([Theme::Light, Theme::Dark].map(event));
+        assert_eq!(filtered(events, Some(Theme::Dark), Duration::from_millis(50)).await, []);
+    }
+
+    #[tokio::test]
+    async fn debounce_passes_events_further_apart() {
+        let (sender, events) = channel();
+        tokio::spawn(async move {
+            sender.send(event(Theme::Light));
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            sender.send(event(Theme::Dark));
+        });
+        assert_eq!(
+            filtered(events, Some(Theme::Dark), Duration::from_millis(50)).await,
+            [(Theme::Light, Some(Theme::Dark)), (Theme::Dark, Some(Theme::Light))]
+        );
+    }
+}
@@ -1,27 +1,146 @@
-use crate::Theme;
-use std::sync::Arc;
-use std::process::Command;
-use std::path::PathBuf;
+use crate::logging::{log_debug, log_error, log_info, log_warn};
+use crate::config_file::{HookCommand, ScriptEntry, ScriptHook, format_duration};
+use crate::worker::Cancellation;
+use crate::{Theme, ThemeEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often running scripts are checked for exit or cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How long a terminated script gets to exit after SIGTERM before SIGKILL
+const TERMINATE_GRACE: Duration = Duration::from_secs(3);
+
+/// How long to keep reading a script's output after it exited. Processes it
+/// left running in the background may hold its pipes open indefinitely.
+pub(crate) const OUTPUT_GRACE: Duration = Duration::from_millis(200);
+
+/// How many of a script's last output lines are kept in its report
+const OUTPUT_TAIL_LINES: usize = 20;
+
+/// Longer output lines are split
+const MAX_LINE_LENGTH: u64 = 4096;
+
+/// How a handler fared with a theme change
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "outcome", rename_all = "lowercase")]
+pub enum HandlerOutcome {
+    Success,
+    Failed { error: String },
+    /// Superseded by a newer theme change before finishing
+    Cancelled,
+    /// Ran past its timeout and was terminated
+    #[serde(rename = "timed_out")]
+    TimedOut { error: String },
+}
+
+impl HandlerOutcome {
+    /// Success if there were no errors, otherwise all of them
+    pub fn from_errors(errors: Vec<String>) -> Self {
+        if errors.is_empty() {
+            HandlerOutcome::Success
+        } else {
+            HandlerOutcome::Failed { error: errors.join("; ") }
+        }
+    }
+    
+    pub fn is_success(&self) -> bool {
+        matches!(self, HandlerOutcome::Success)
+    }
+    
+    /// Combines the outcomes of several steps; cancellation wins over
+    /// failure, and the result only counts as timed out if every failing
+    /// step timed out
+    pub fn combine(outcomes: impl IntoIterator<Item = (String, HandlerOutcome)>) -> Self {
+        let mut errors = Vec::new();
+        let mut timed_out = true;
+        for (step, outcome) in outcomes {
+            match outcome {
+                HandlerOutcome::Success => {}
+                HandlerOutcome::Failed { error } => {
+                    errors.push(format!("{}: {}", step, error));
+                    timed_out = false;
+                }
+                HandlerOutcome::TimedOut { error } => errors.push(format!("{}: {}", step, error)),
+                HandlerOutcome::Cancelled => return HandlerOutcome::Cancelled,
+            }
+        }
+        
+        if timed_out && !errors.is_empty() {
+            return HandlerOutcome::TimedOut { error: errors.join("; ") };
+        }
+        Self::from_errors(errors)
+    }
+}
+
+/// The outcome of one named handler
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HandlerReport {
+    pub handler: String,
+    #[serde(flatten)]
+    pub outcome: HandlerOutcome,
+    /// The last lines printed by the commands the handler ran
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<String>,
+}
+
+impl HandlerReport {
+    pub fn new(handler: &str, outcome: HandlerOutcome) -> Self {
+        Self {
+            handler: handler.to_string(),
+            outcome,
+            output: Vec::new(),
+        }
+    }
+}
 
 pub trait ThemeHandler: Send + Sync {
-    fn on_theme_change(&self, theme: Theme);
+    /// Short name identifying the handler in reports
+    fn name(&self) -> &str;
+    
+    fn on_theme_change(&self, event: &ThemeEvent) -> HandlerOutcome;
+    
+    /// Like `on_theme_change`, but gives up once `cancel` is triggered.
+    /// Handlers running child processes override this to terminate them.
+    fn on_theme_change_cancellable(&self, event: &ThemeEvent, cancel: &Cancellation) -> HandlerOutcome {
+        if cancel.is_cancelled() {
+            return HandlerOutcome::Cancelled;
+        }
+        self.on_theme_change(event)
+    }
+    
+    /// Runs the handler, reporting the outcome of each handler it is made of
+    fn apply(&self, event: &ThemeEvent, cancel: &Cancellation) -> Vec<HandlerReport> {
+        vec![HandlerReport::new(self.name(), self.on_theme_change_cancellable(event, cancel))]
+    }
 }
 
 pub struct LoggingThemeHandler;
 
 impl ThemeHandler for LoggingThemeHandler {
-    fn on_theme_change(&self, theme: Theme) {
-        println!("Theme changed to: {}", theme);
-        match theme {
+    fn name(&self) -> &str {
+        "logging"
+    }
+    
+    fn on_theme_change(&self, event: &ThemeEvent) -> HandlerOutcome {
+        log_info(&format!("Theme changed to: {} (source: {})", event.theme, event.source));
+        match event.theme {
             Theme::Light => {
-                println!("Executing light theme actions...");
+                log_info("Executing light theme actions...");
                 // TODO: Execute light theme commands
             }
             Theme::Dark => {
-                println!("Executing dark theme actions...");
+                log_info("Executing dark theme actions...");
                 // TODO: Execute dark theme commands
             }
         }
+        HandlerOutcome::Success
     }
 }
 
@@ -48,18 +167,39 @@ impl Default for CompositeThemeHandler {
 }
 
 impl ThemeHandler for CompositeThemeHandler {
-    fn on_theme_change(&self, theme: Theme) {
-        for handler in &self.handlers {
-            handler.on_theme_change(theme);
-        }
+    fn name(&self) -> &str {
+        "composite"
+    }
+    
+    fn on_theme_change(&self, event: &ThemeEvent) -> HandlerOutcome {
+        self.on_theme_change_cancellable(event, &Cancellation::new())
+    }
+    
+    fn on_theme_change_cancellable(&self, event: &ThemeEvent, cancel: &Cancellation) -> HandlerOutcome {
+        let reports = self.apply(event, cancel);
+        HandlerOutcome::combine(reports.into_iter().map(|report| (report.handler, report.outcome)))
+    }
+    
+    fn apply(&self, event: &ThemeEvent, cancel: &Cancellation) -> Vec<HandlerReport> {
+        // Handlers after a cancellation are reported as cancelled
+        self.handlers.iter().flat_map(|handler| handler.apply(event, cancel)).collect()
     }
 }
 
-/// Handler that executes shell scripts when theme changes
+/// Handler that executes shell scripts when theme changes.
+///
+/// Entries run concurrently, up to `max_parallel` at a time; the scripts of a
+/// sequence entry run in order, and stop at the first failure. Each script
+/// runs in its own process group, which is terminated as a whole on timeout
+/// or cancellation. Script output is logged line by line, tagged with the
+/// script and stream.
 pub struct ScriptHandler {
-    light_scripts: Vec<PathBuf>,
-    dark_scripts: Vec<PathBuf>,
-    any_change_scripts: Vec<PathBuf>,
+    light_scripts: Vec<ScriptEntry>,
+    dark_scripts: Vec<ScriptEntry>,
+    any_change_scripts: Vec<ScriptEntry>,
+    max_parallel: usize,
+    timeout: Option<Duration>,
+    output_limit: usize,
 }
 
 impl ScriptHandler {
@@ -68,66 +208,396 @@ impl ScriptHandler {
             light_scripts: Vec::new(),
             dark_scripts: Vec::new(),
             any_change_scripts: Vec::new(),
+            max_parallel: 1,
+            timeout: None,
+            output_limit: 64 * 1024,
         }
     }
     
-    pub fn with_light_script(mut self, path: PathBuf) -> Self {
-        self.light_scripts.push(path);
+    /// Runs `script` with `sh -c`, so like the `--light-script` value it
+    /// was taken from it may carry arguments or shell syntax
+    pub fn with_light_script(mut self, script: PathBuf) -> Self {
+        self.light_scripts.push(shell_entry(&script));
         self
     }
     
-    pub fn with_dark_script(mut self, path: PathBuf) -> Self {
-        self.dark_scripts.push(path);
+    /// Runs `script` with `sh -c`, see [`Self::with_light_script`]
+    pub fn with_dark_script(mut self, script: PathBuf) -> Self {
+        self.dark_scripts.push(shell_entry(&script));
         self
     }
     
-    pub fn with_any_change_script(mut self, path: PathBuf) -> Self {
-        self.any_change_scripts.push(path);
+    /// Runs `script` with `sh -c`, see [`Self::with_light_script`]
+    pub fn with_any_change_script(mut self, script: PathBuf) -> Self {
+        self.any_change_scripts.push(shell_entry(&script));
         self
     }
     
-    pub fn with_light_scripts(mut self, paths: Vec<PathBuf>) -> Self {
-        self.light_scripts.extend(paths);
+    pub fn with_light_scripts(mut self, entries: Vec<ScriptEntry>) -> Self {
+        self.light_scripts.extend(entries);
         self
     }
     
-    pub fn with_dark_scripts(mut self, paths: Vec<PathBuf>) -> Self {
-        self.dark_scripts.extend(paths);
+    pub fn with_dark_scripts(mut self, entries: Vec<ScriptEntry>) -> Self {
+        self.dark_scripts.extend(entries);
         self
     }
     
-    pub fn with_any_change_scripts(mut self, paths: Vec<PathBuf>) -> Self {
-        self.any_change_scripts.extend(paths);
+    pub fn with_any_change_scripts(mut self, entries: Vec<ScriptEntry>) -> Self {
+        self.any_change_scripts.extend(entries);
         self
     }
     
-    fn execute_script(&self, script_path: &PathBuf, theme: Theme) {
-        use crate::config::log_info;
+    /// Lets up to `max_parallel` entries run at once (at least one)
+    pub fn with_max_parallel(mut self, max_parallel: usize) -> Self {
+        self.max_parallel = max_parallel.max(1);
+        self
+    }
+    
+    /// Terminates scripts running longer than `timeout`, unless they set
+    /// their own
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+    
+    /// Stops logging a script's output after `bytes`; its last lines are
+    /// still kept for the report
+    pub fn with_output_limit(mut self, bytes: usize) -> Self {
+        self.output_limit = bytes;
+        self
+    }
+    
+    /// Runs the scripts for the event, returning the combined outcome and
+    /// the tail of their output
+    fn run(&self, event: &ThemeEvent, cancel: &Cancellation) -> (HandlerOutcome, Vec<String>) {
+        // Theme-specific scripts are picked up first, then any-change scripts
+        let scripts = match event.theme {
+            Theme::Light => &self.light_scripts,
+            Theme::Dark => &self.dark_scripts,
+        };
+        
+        let entries: Vec<&ScriptEntry> = scripts.iter().chain(&self.any_change_scripts).collect();
+        let runs = self.run_entries(&entries, event, cancel);
+        let output = runs.iter().flat_map(|run| run.output.iter().cloned()).collect();
+        let outcome = HandlerOutcome::combine(runs.into_iter().map(|run| (run.name, run.outcome)));
+        (outcome, output)
+    }
+    
+    /// Runs the entries on up to `max_parallel` threads and returns every
+    /// script's run in entry order, once all have finished
+    fn run_entries(&self, entries: &[&ScriptEntry], event: &ThemeEvent, cancel: &Cancellation) -> Vec<ScriptRun> {
+        let next = AtomicUsize::new(0);
+        let results = Mutex::new(vec![Vec::new(); entries.len()]);
         
-        log_info(&format!("Executing script: {:?}", script_path));
+        std::thread::scope(|scope| {
+            for _ in 0..self.max_parallel.min(entries.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(entry) = entries.get(index) else {
+                        break;
+                    };
+                    let outcomes = self.run_entry(entry, event, cancel);
+                    results.lock().unwrap()[index] = outcomes;
+                });
+            }
+        });
         
-        let result = Command::new("sh")
-            .arg("-c")
-            .arg(script_path.to_string_lossy().as_ref())
-            .env("THEME_SWITCHER_THEME", theme.to_string())
-            .env("THEME_SWITCHER_THEME_UPPER", theme.to_string().to_uppercase())
-            .spawn();
+        results.into_inner().unwrap().into_iter().flatten().collect()
+    }
+    
+    /// Runs an entry's scripts in order, skipping the rest after a failure
+    fn run_entry(&self, entry: &ScriptEntry, event: &ThemeEvent, cancel: &Cancellation) -> Vec<ScriptRun> {
+        let mut runs = Vec::new();
+        let mut scripts = entry.scripts().iter();
+        
+        for script in scripts.by_ref() {
+            let mut output = Vec::new();
+            let outcome = self.execute_script(script, event, cancel, &mut output);
+            let success = outcome.is_success();
+            runs.push(ScriptRun { name: script.name(), outcome, output });
+            if !success {
+                break;
+            }
+        }
+        
+        if !cancel.is_cancelled() {
+            for script in scripts {
+                let error = "Skipped after an earlier script in its sequence failed".to_string();
+                runs.push(ScriptRun {
+                    name: script.name(),
+                    outcome: HandlerOutcome::Failed { error },
+                    output: Vec::new(),
+                });
+            }
+        }
+        runs
+    }
+    
+    /// Runs a script, storing the tail of its output in `output`
+    fn execute_script(&self, script: &ScriptHook, event: &ThemeEvent, cancel: &Cancellation, output: &mut Vec<String>) -> HandlerOutcome {
+        if cancel.is_cancelled() {
+            return HandlerOutcome::Cancelled;
+        }
+        let script_name = script.name();
+        log_info(&format!("Executing script: {:?}", script_name));
+        
+        let mut command = match script.command {
+            // The path is passed as `$0` rather than spliced into the
+            // command line, so spaces and quotes in it are kept
+            HookCommand::Script { ref path, shell: Some(ref shell) } => {
+                let mut command = Command::new(shell);
+                command.arg("-c").arg(r#"exec "$0" "$@""#).arg(path);
+                command
+            }
+            HookCommand::Script { ref path, shell: None } => Command::new(path),
+            HookCommand::Shell { ref shell, command: ref line } => {
+                let mut command = Command::new(shell);
+                command.arg("-c").arg(line);
+                command
+            }
+            HookCommand::Exec(ref args) => {
+                let mut command = Command::new(&args[0]);
+                command.args(&args[1..]);
+                command
+            }
+        };
+        command
+            .env("THEME_SWITCHER_THEME", event.theme.to_string())
+            .env("THEME_SWITCHER_THEME_UPPER", event.theme.to_string().to_uppercase())
+            .env("THEME_SWITCHER_SOURCE", event.source.to_string())
+            .env("THEME_SWITCHER_TIMESTAMP", event.timestamp.to_rfc3339());
+        
+        // Unset on the first change when the previous theme is unknown
+        match event.previous {
+            Some(previous) => command.env("THEME_SWITCHER_PREVIOUS_THEME", previous.to_string()),
+            None => command.env_remove("THEME_SWITCHER_PREVIOUS_THEME"),
+        };
+        
+        command.envs(&script.env);
+        if let Some(ref cwd) = script.cwd {
+            command.current_dir(cwd);
+        }
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        
+        // Lets the script and everything it starts be signalled together
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        
+        let result = command.spawn();
+        let timeout = script.timeout.or(self.timeout);
             
-        match result {
-            Ok(mut child) => {
-                // Don't wait for the script to complete - run it in background
-                match child.wait() {
-                    Ok(status) => {
-                        if !status.success() {
-                            eprintln!("Script exited with non-zero status: {:?}", status);
-                        }
-                    }
-                    Err(e) => eprintln!("Failed to wait for script: {}", e),
+        let error = match result {
+            Ok(mut child) => match self.wait_capturing(&mut child, script, timeout, cancel, output) {
+                Ok(ScriptExit::Exited(status)) if status.success() => {
+                    log_debug(&format!("Script finished: {:?}", script_name));
+                    return HandlerOutcome::Success;
+                }
+                Ok(ScriptExit::Exited(status)) => format!("Script exited with non-zero status: {:?}", status),
+                Ok(ScriptExit::Cancelled) => {
+                    log_info(&format!("Terminated superseded script: {:?}", script_name));
+                    return HandlerOutcome::Cancelled;
+                }
+                Ok(ScriptExit::TimedOut(timeout)) => {
+                    let error = format!("Script timed out after {}", format_duration(timeout));
+                    log_error(&format!("{}: {:?}", error, script_name));
+                    return HandlerOutcome::TimedOut { error };
+                }
+                Err(e) => format!("Failed to wait for script: {}", e),
+            },
+            Err(e) => format!("Failed to execute script: {}", e),
+        };
+        
+        log_error(&error);
+        HandlerOutcome::Failed { error }
+    }
+    
+    /// Waits for the script like `wait_for_script` while logging its output,
+    /// then stores the output's tail in `output`
+    fn wait_capturing(
+        &self,
+        child: &mut Child,
+        script: &ScriptHook,
+        timeout: Option<Duration>,
+        cancel: &Cancellation,
+        output: &mut Vec<String>,
+    ) -> std::io::Result<ScriptExit> {
+        let capture = Arc::new(Mutex::new(OutputCapture::new(script.label(), self.output_limit)));
+        let (done_sender, done) = mpsc::channel();
+        if let Some(stdout) = child.stdout.take() {
+            read_output(stdout, "stdout", log_info, Arc::clone(&capture), done_sender.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_output(stderr, "stderr", log_warn, Arc::clone(&capture), done_sender.clone());
+        }
+        drop(done_sender);
+        
+        let exit = wait_for_script(child, timeout, cancel);
+        
+        // Readers still blocked after the grace period are left to finish
+        // logging on their own
+        let deadline = Instant::now() + OUTPUT_GRACE;
+        while done.recv_timeout(deadline.saturating_duration_since(Instant::now())).is_ok() {}
+        
+        output.extend(capture.lock().unwrap().tail.iter().cloned());
+        exit
+    }
+}
+
+fn shell_entry(script: &Path) -> ScriptEntry {
+    ScriptEntry::Script(ScriptHook::shell_command(script.to_string_lossy()))
+}
+
+/// One script's part in a run of the handler
+#[derive(Clone)]
+struct ScriptRun {
+    name: String,
+    outcome: HandlerOutcome,
+    /// The last lines of its output
+    output: Vec<String>,
+}
+
+/// A script's output as it is read
+struct OutputCapture {
+    label: String,
+    /// Bytes that may still be logged
+    remaining: usize,
+    truncated: bool,
+    tail: VecDeque<String>,
+}
+
+impl OutputCapture {
+    fn new(label: String, limit: usize) -> Self {
+        Self {
+            label,
+            remaining: limit,
+            truncated: false,
+            tail: VecDeque::with_capacity(OUTPUT_TAIL_LINES),
+        }
+    }
+    
+    /// Logs a line with `log` until the limit is reached, and keeps it in
+    /// the tail either way
+    fn push(&mut self, stream: &str, log: fn(&str), line: &str) {
+        let tagged = format!("[{}:{}] {}", self.label, stream, line);
+        
+        if !self.truncated {
+            match self.remaining.checked_sub(line.len() + 1) {
+                Some(remaining) => {
+                    self.remaining = remaining;
+                    log(&tagged);
+                }
+                None => {
+                    self.truncated = true;
+                    log_warn(&format!("[{}] Output truncated", self.label));
                 }
             }
-            Err(e) => eprintln!("Failed to execute script: {}", e),
         }
+        
+        if self.tail.len() == OUTPUT_TAIL_LINES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(tagged);
+    }
+}
+
+/// Reads `pipe` line by line into `capture` on a new thread, signalling
+/// `done` at the end of the output
+fn read_output<R: Read + Send + 'static>(
+    pipe: R,
+    stream: &'static str,
+    log: fn(&str),
+    capture: Arc<Mutex<OutputCapture>>,
+    done: mpsc::Sender<()>,
+) {
+    std::thread::spawn(move || {
+        let mut reader = BufReader::new(pipe);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.by_ref().take(MAX_LINE_LENGTH).read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&line);
+                    capture.lock().unwrap().push(stream, log, text.trim_end_matches(['\n', '\r']));
+                }
+            }
+        }
+        let _ = done.send(());
+    });
+}
+
+/// How a script run ended
+pub(crate) enum ScriptExit {
+    Exited(ExitStatus),
+    /// Terminated because `cancel` was triggered
+    Cancelled,
+    /// Terminated after running for the given timeout
+    TimedOut(Duration),
+}
+
+/// Waits for `child` to exit, terminating it if `cancel` is triggered or
+/// `timeout` passes first
+pub(crate) fn wait_for_script(child: &mut Child, timeout: Option<Duration>, cancel: &Cancellation) -> std::io::Result<ScriptExit> {
+    let started = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(ScriptExit::Exited(status));
+        }
+        if cancel.is_cancelled() {
+            terminate(child)?;
+            return Ok(ScriptExit::Cancelled);
+        }
+        if let Some(timeout) = timeout.filter(|timeout| started.elapsed() >= *timeout) {
+            terminate(child)?;
+            return Ok(ScriptExit::TimedOut(timeout));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Sends SIGTERM to the child's process group, then SIGKILL to whatever is
+/// left of it after `TERMINATE_GRACE`
+#[cfg(unix)]
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    // The child leads its own group, so the group id is its pid
+    let group = child.id() as libc::pid_t;
+    let signal_group = |signal| unsafe { libc::kill(-group, signal) == 0 };
+    
+    signal_group(libc::SIGTERM);
+    let deadline = Instant::now() + TERMINATE_GRACE;
+    while Instant::now() < deadline {
+        // Reap the script itself, then wait for the rest of the group
+        let exited = child.try_wait()?.is_some();
+        if exited && !signal_group(0) {
+            return Ok(());
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    
+    signal_group(libc::SIGKILL);
+    child.wait()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child) -> std::io::Result<()> {
+    child.kill()?;
+    child.wait()?;
+    Ok(())
+}
+
+/// Reads `pipe` to the end on a new thread
+pub(crate) fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    if let Some(mut pipe) = pipe {
+        std::thread::spawn(move || {
+            let mut bytes = Vec::new();
+            let _ = pipe.read_to_end(&mut bytes);
+            let _ = sender.send(bytes);
+        });
     }
+    receiver
 }
 
 impl Default for ScriptHandler {
@@ -137,24 +607,236 @@ impl Default for ScriptHandler {
 }
 
 impl ThemeHandler for ScriptHandler {
-    fn on_theme_change(&self, theme: Theme) {
-        // Execute theme-specific scripts
-        match theme {
-            Theme::Light => {
-                for script in &self.light_scripts {
-                    self.execute_script(script, theme);
-                }
-            }
-            Theme::Dark => {
-                for script in &self.dark_scripts {
-                    self.execute_script(script, theme);
-                }
+    fn name(&self) -> &str {
+        "scripts"
+    }
+    
+    fn on_theme_change(&self, event: &ThemeEvent) -> HandlerOutcome {
+        self.on_theme_change_cancellable(event, &Cancellation::new())
+    }
+    
+    fn on_theme_change_cancellable(&self, event: &ThemeEvent, cancel: &Cancellation) -> HandlerOutcome {
+        self.run(event, cancel).0
+    }
+    
+    fn apply(&self, event: &ThemeEvent, cancel: &Cancellation) -> Vec<HandlerReport> {
+        let (outcome, output) = self.run(event, cancel);
+        vec![HandlerReport {
+            handler: self.name().to_string(),
+            outcome,
+            output,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ThemeSource;
+    use std::os::unix::fs::PermissionsExt;
+    
+    /// An empty directory for one test, with a space in its path
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("theme-switcher {} {}", test, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    /// Writes an executable script that records the theme in `ran`
+    fn write_script(dir: &std::path::Path) -> PathBuf {
+        let script = dir.join("dark script.sh");
+        std::fs::write(&script, "#!/bin/sh\necho \"$THEME_SWITCHER_THEME\" > \"$(dirname \"$0\")/ran\"\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        script
+    }
+    
+    fn dark() -> ThemeEvent {
+        ThemeEvent::new(Theme::Dark, ThemeSource::Manual)
+    }
+    
+    #[test]
+    fn runs_script_with_space_in_path() {
+        let dir = temp_dir("direct");
+        let handler = ScriptHandler::new().with_dark_scripts(vec![write_script(&dir).into()]);
+        
+        let outcome = handler.on_theme_change(&dark());
+        assert!(outcome.is_success(), "{:?}", outcome);
+        assert_eq!(std::fs::read_to_string(dir.join("ran")).unwrap(), "dark\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn command_line_scripts_run_through_the_shell() {
+        let dir = temp_dir("command line");
+        let line = format!("echo \"$THEME_SWITCHER_THEME\" > '{}'", dir.join("ran").display());
+        let handler = ScriptHandler::new().with_dark_script(PathBuf::from(line));
+        
+        let outcome = handler.on_theme_change(&dark());
+        assert!(outcome.is_success(), "{:?}", outcome);
+        assert_eq!(std::fs::read_to_string(dir.join("ran")).unwrap(), "dark\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    #[test]
+    fn runs_script_with_space_in_path_through_shell() {
+        let dir = temp_dir("shell");
+        let hook = ScriptHook {
+            command: HookCommand::Script {
+                path: write_script(&dir),
+                shell: Some(PathBuf::from("sh")),
+            },
+            env: Default::default(),
+            cwd: None,
+            timeout: None,
+        };
+        let handler = ScriptHandler::new().with_dark_scripts(vec![ScriptEntry::Script(hook)]);
+        
+        let outcome = handler.on_theme_change(&dark());
+        assert!(outcome.is_success(), "{:?}", outcome);
+        assert_eq!(std::fs::read_to_string(dir.join("ran")).unwrap(), "dark\n");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    /// A shell command run in `dir`
+    fn shell_hook(dir: &std::path::Path, line: &str) -> ScriptHook {
+        ScriptHook {
+            command: HookCommand::Shell {
+                shell: PathBuf::from("sh"),
+                command: line.to_string(),
+            },
+            env: Default::default(),
+            cwd: Some(dir.to_path_buf()),
+            timeout: None,
+        }
+    }
+    
+    /// The most scripts that were running at once, from a log of `+` on
+    /// start and `-` on exit
+    fn max_running(log: &str) -> usize {
+        let (mut running, mut max) = (0usize, 0);
+        for line in log.lines() {
+            if line == "+" {
+                running += 1;
+                max = max.max(running);
+            } else {
+                running -= 1;
             }
         }
+        max
+    }
+    
+    #[test]
+    fn max_parallel_bounds_concurrent_entries() {
+        for max_parallel in [1, 2] {
+            let dir = temp_dir(&format!("parallel {}", max_parallel));
+            let entries: Vec<ScriptEntry> = (0..4)
+                .map(|_| ScriptEntry::Script(shell_hook(&dir, "echo + >> log; sleep 0.3; echo - >> log")))
+                .collect();
+            let handler = ScriptHandler::new()
+                .with_dark_scripts(entries)
+                .with_max_parallel(max_parallel);
+            
+            let outcome = handler.on_theme_change(&dark());
+            assert!(outcome.is_success(), "{:?}", outcome);
+            let log = std::fs::read_to_string(dir.join("log")).unwrap();
+            assert_eq!(log.lines().count(), 8);
+            assert_eq!(max_running(&log), max_parallel);
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+    
+    #[test]
+    fn sequence_runs_in_order_and_stops_after_a_failure() {
+        let dir = temp_dir("sequence");
+        let sequence = ScriptEntry::Sequence(vec![
+            shell_hook(&dir, "sleep 0.2; echo first >> log"),
+            shell_hook(&dir, "echo second >> log; exit 3"),
+            shell_hook(&dir, "echo third >> log"),
+        ]);
+        let handler = ScriptHandler::new().with_max_parallel(4);
         
-        // Execute any-change scripts
-        for script in &self.any_change_scripts {
-            self.execute_script(script, theme);
+        let runs = handler.run_entries(&[&sequence], &dark(), &Cancellation::new());
+        assert_eq!(std::fs::read_to_string(dir.join("log")).unwrap(), "first\nsecond\n");
+        let outcomes: Vec<bool> = runs.iter().map(|run| run.outcome.is_success()).collect();
+        assert_eq!(outcomes, [true, false, false]);
+        assert!(matches!(runs[2].outcome, HandlerOutcome::Failed { ref error } if error.starts_with("Skipped")));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    /// Whether `pid` is alive, not counting zombies nobody reaped yet
+    fn is_running(pid: &str) -> bool {
+        match std::fs::read_to_string(format!("/proc/{}/stat", pid)) {
+            Ok(stat) => !stat.rsplit_once(") ").is_some_and(|(_, rest)| rest.starts_with('Z')),
+            Err(_) => false,
         }
     }
+    
+    #[test]
+    fn timeout_terminates_the_whole_process_group() {
+        let dir = temp_dir("timeout");
+        let mut command = Command::new("sh");
+        command
+            .arg("-c")
+            .arg("sleep 30 & echo $! > pid; wait")
+            .current_dir(&dir);
+        std::os::unix::process::CommandExt::process_group(&mut command, 0);
+        let mut child = command.spawn().unwrap();
+        
+        let timeout = Duration::from_millis(300);
+        let started = Instant::now();
+        let exit = wait_for_script(&mut child, Some(timeout), &Cancellation::new()).unwrap();
+        assert!(matches!(exit, ScriptExit::TimedOut(t) if t == timeout));
+        // Allows for polling and a slow reaper on top of the grace period
+        assert!(started.elapsed() < timeout + TERMINATE_GRACE + Duration::from_secs(1));
+        
+        let pid = std::fs::read_to_string(dir.join("pid")).unwrap();
+        assert!(!is_running(pid.trim()), "sleep {} outlived its script", pid.trim());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+    
+    thread_local! {
+        static LOGGED: std::cell::RefCell<Vec<String>> = const { std::cell::RefCell::new(Vec::new()) };
+    }
+    
+    /// Records what `OutputCapture` logs on this thread
+    fn record(line: &str) {
+        LOGGED.with(|logged| logged.borrow_mut().push(line.to_string()));
+    }
+    
+    #[test]
+    fn output_beyond_the_limit_is_not_logged() {
+        // Each line takes six bytes with its newline
+        let mut capture = OutputCapture::new("dark.sh".to_string(), 20);
+        for n in 1..=5 {
+            capture.push("stdout", record, &format!("line{}", n));
+        }
+        
+        let logged = LOGGED.with(|logged| logged.take());
+        assert_eq!(logged, ["[dark.sh:stdout] line1", "[dark.sh:stdout] line2", "[dark.sh:stdout] line3"]);
+        assert!(capture.truncated);
+        assert_eq!(capture.tail.len(), 5);
+        assert_eq!(capture.tail.back().unwrap(), "[dark.sh:stdout] line5");
+    }
+    
+    #[test]
+    fn failed_script_reports_the_tail_of_its_output() {
+        let dir = temp_dir("tail");
+        let script = dir.join("noisy.sh");
+        // The pause keeps stderr from overtaking stdout in the tail
+        std::fs::write(&script, "#!/bin/sh\nseq 1 30\nsleep 0.2\necho oops >&2\nexit 1\n").unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let handler = ScriptHandler::new()
+            .with_dark_scripts(vec![script.into()])
+            .with_output_limit(16);
+        
+        let reports = handler.apply(&dark(), &Cancellation::new());
+        assert!(matches!(reports[0].outcome, HandlerOutcome::Failed { .. }));
+        let output = &reports[0].output;
+        assert_eq!(output.len(), OUTPUT_TAIL_LINES);
+        assert_eq!(output.first().unwrap(), "[noisy.sh:stdout] 12");
+        assert_eq!(output[OUTPUT_TAIL_LINES - 2], "[noisy.sh:stdout] 30");
+        assert_eq!(output.last().unwrap(), "[noisy.sh:stderr] oops");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
@@ -1,31 +1,53 @@
-use std::error::Error;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
 use tokio::net::{UnixListener, UnixStream};
 use tokio::io::{AsyncWriteExt, AsyncBufReadExt, BufReader};
 use tokio::sync::broadcast;
-use crate::{Theme, config};
+use crate::error::{Result, ThemeSwitcherError};
+use crate::{Theme, logging};
+use crate::events::{EventSender, ThemeEvent, ThemeSource};
+
+/// How a client wants theme changes written to it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum WireFormat {
+    /// One bare `light` or `dark` line per change
+    Plain,
+    /// One JSON object per change, with the full event
+    Json,
+}
+
+/// Where the daemon listens: `theme-switcher.sock` in the runtime directory
+pub fn socket_path() -> PathBuf {
+    dirs::runtime_dir()
+        .or_else(|| dirs::home_dir().map(|h| h.join(".local/run")))
+        .unwrap_or_else(std::env::temp_dir)
+        .join("theme-switcher.sock")
+}
 
 pub struct IpcServer {
     socket_path: String,
-    sender: broadcast::Sender<Theme>,
+    sender: broadcast::Sender<ThemeEvent>,
     current_theme: Arc<RwLock<Theme>>,
+    events: Option<EventSender>,
 }
 
 impl IpcServer {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
-        let socket_dir = dirs::runtime_dir()
-            .or_else(|| dirs::home_dir().map(|h| h.join(".local/run")))
-            .unwrap_or_else(|| std::env::temp_dir());
+    pub fn new() -> Result<Self> {
+        let socket_path = socket_path();
         
         // Create directory if it doesn't exist
-        std::fs::create_dir_all(&socket_dir)?;
+        if let Some(socket_dir) = socket_path.parent() {
+            std::fs::create_dir_all(socket_dir)
+                .map_err(|e| ThemeSwitcherError::Ipc(format!("Failed to create {:?}: {}", socket_dir, e)))?;
+        }
         
-        let socket_path = socket_dir
-            .join("theme-switcher.sock")
-            .to_string_lossy()
-            .to_string();
+        let socket_path = socket_path.to_string_lossy().to_string();
 
-        // Remove existing socket if it exists
+        // A socket that still accepts connections belongs to a running
+        // daemon; one left behind by a crash is removed
+        if std::os::unix::net::UnixStream::connect(&socket_path).is_ok() {
+            return Err(ThemeSwitcherError::AlreadyRunning(socket_path.into()));
+        }
         let _ = std::fs::remove_file(&socket_path);
 
         let (sender, _) = broadcast::channel(16);
@@ -34,10 +56,17 @@ impl IpcServer {
             socket_path,
             sender,
             current_theme: Arc::new(RwLock::new(Theme::Dark)), // Default, will be updated
+            events: None,
         })
     }
 
-    pub fn get_broadcaster(&self) -> broadcast::Sender<Theme> {
+    /// Lets clients switch the theme with `set light` or `set dark`
+    pub fn with_events(mut self, events: EventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn get_broadcaster(&self) -> broadcast::Sender<ThemeEvent> {
         self.sender.clone()
     }
     
@@ -51,30 +80,35 @@ impl IpcServer {
         self.current_theme.clone()
     }
 
-    pub async fn start(&self) -> Result<(), Box<dyn Error>> {
-        let listener = UnixListener::bind(&self.socket_path)?;
-        config::log_info(&format!("IPC server listening on: {}", self.socket_path));
+    pub async fn start(&self) -> Result<()> {
+        let listener = UnixListener::bind(&self.socket_path)
+            .map_err(|e| ThemeSwitcherError::Ipc(format!("Failed to listen on {}: {}", self.socket_path, e)))?;
+        logging::log_info(&format!("IPC server listening on: {}", self.socket_path));
 
         // Set permissions to allow user access
         #[cfg(unix)]
         {
             use std::os::unix::fs::PermissionsExt;
-            std::fs::set_permissions(&self.socket_path, std::fs::Permissions::from_mode(0o600))?;
+            std::fs::set_permissions(&self.socket_path, std::fs::Permissions::from_mode(0o600))
+                .map_err(|e| ThemeSwitcherError::Ipc(format!("Failed to restrict {}: {}", self.socket_path, e)))?;
         }
 
         let sender = self.sender.clone();
         let current_theme = self.current_theme.clone();
+        let events = self.events.clone();
 
         tokio::spawn(async move {
             loop {
                 match listener.accept().await {
                     Ok((stream, _)) => {
+                        logging::log_debug("IPC client connected");
                         let sender = sender.clone();
                         let current_theme = current_theme.clone();
-                        tokio::spawn(handle_client(stream, sender, current_theme));
+                        let events = events.clone();
+                        tokio::spawn(handle_client(stream, sender, current_theme, events));
                     }
                     Err(e) => {
-                        eprintln!("Error accepting connection: {}", e);
+                        logging::log_error(&format!("Error accepting connection: {}", e));
                     }
                 }
             }
@@ -88,8 +122,31 @@ impl IpcServer {
     }
 }
 
-async fn handle_client(mut stream: UnixStream, sender: broadcast::Sender<Theme>, current_theme: Arc<RwLock<Theme>>) {
+fn format_event(event: &ThemeEvent, format: WireFormat) -> String {
+    match format {
+        WireFormat::Plain => format!("{}\n", event.theme),
+        WireFormat::Json => match serde_json::to_string(event) {
+            Ok(json) => format!("{}\n", json),
+            Err(e) => format!("{{\"error\":{:?}}}\n", e.to_string()),
+        },
+    }
+}
+
+fn format_theme(theme: Theme, format: WireFormat) -> String {
+    match format {
+        WireFormat::Plain => format!("{}\n", theme),
+        WireFormat::Json => format!("{}\n", serde_json::json!({ "theme": theme })),
+    }
+}
+
+async fn handle_client(
+    mut stream: UnixStream,
+    sender: broadcast::Sender<ThemeEvent>,
+    current_theme: Arc<RwLock<Theme>>,
+    events: Option<EventSender>,
+) {
     let mut receiver = sender.subscribe();
+    let mut format = WireFormat::Plain;
     
     // Send current theme immediately upon connection
     let theme_to_send = {
@@ -97,7 +154,7 @@ async fn handle_client(mut stream: UnixStream, sender: broadcast::Sender<Theme>,
     };
     
     if let Some(theme) = theme_to_send {
-        let _ = stream.write_all(format!("{}\n", theme).as_bytes()).await;
+        let _ = stream.write_all(format_theme(theme, format).as_bytes()).await;
     }
 
     // Create a reader for incoming commands
@@ -108,22 +165,47 @@ async fn handle_client(mut stream: UnixStream, sender: broadcast::Sender<Theme>,
     loop {
         tokio::select! {
             // Handle incoming theme changes
-            theme_result = receiver.recv() => {
-                match theme_result {
-                    Ok(theme) => {
-                        if let Err(_) = writer.write_all(format!("{}\n", theme).as_bytes()).await {
+            event_result = receiver.recv() => {
+                match event_result {
+                    Ok(event) => {
+                        if writer.write_all(format_event(&event, format).as_bytes()).await.is_err() {
                             break; // Client disconnected
                         }
                     }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
                     Err(_) => break, // Channel closed
                 }
             }
-            // Handle client commands (like "quit")
+            // Handle client commands
             read_result = reader.read_line(&mut line) => {
                 match read_result {
                     Ok(0) => break, // Client disconnected
                     Ok(_) => {
-                        if line.trim() == "quit" {
+                        let mut words = line.split_whitespace();
+                        let reply = match (words.next(), words.next()) {
+                            (Some("quit"), None) => break,
+                            // Switch to JSON events; replies with the current theme
+                            (Some("format"), Some("json")) => {
+                                format = WireFormat::Json;
+                                current_theme.read().ok().map(|t| format_theme(*t, format))
+                            }
+                            (Some("format"), Some("plain")) => {
+                                format = WireFormat::Plain;
+                                current_theme.read().ok().map(|t| format_theme(*t, format))
+                            }
+                            (Some("set"), Some(theme)) => match (theme.parse::<Theme>(), &events) {
+                                (Ok(theme), Some(events)) => {
+                                    events.send(ThemeEvent::new(theme, ThemeSource::Ipc));
+                                    None
+                                }
+                                (Err(e), _) => Some(error_line(&e, format)),
+                                (_, None) => Some(error_line("Setting the theme is not supported", format)),
+                            },
+                            _ => Some(error_line(&format!("Unknown command: {:?}", line.trim()), format)),
+                        };
+                        if let Some(reply) = reply
+                            && writer.write_all(reply.as_bytes()).await.is_err()
+                        {
                             break;
                         }
                         line.clear();
@@ -135,24 +217,36 @@ async fn handle_client(mut stream: UnixStream, sender: broadcast::Sender<Theme>,
     }
 }
 
+fn error_line(message: &str, format: WireFormat) -> String {
+    match format {
+        WireFormat::Plain => format!("error: {}\n", message),
+        WireFormat::Json => format!("{}\n", serde_json::json!({ "error": message })),
+    }
+}
+
 pub struct IpcHandler {
-    sender: broadcast::Sender<Theme>,
+    sender: broadcast::Sender<ThemeEvent>,
     current_theme: Arc<RwLock<Theme>>,
 }
 
 impl IpcHandler {
-    pub fn new(sender: broadcast::Sender<Theme>, current_theme: Arc<RwLock<Theme>>) -> Self {
+    pub fn new(sender: broadcast::Sender<ThemeEvent>, current_theme: Arc<RwLock<Theme>>) -> Self {
         Self { sender, current_theme }
     }
 }
 
 impl crate::handlers::ThemeHandler for IpcHandler {
-    fn on_theme_change(&self, theme: Theme) {
+    fn name(&self) -> &str {
+        "ipc"
+    }
+    
+    fn on_theme_change(&self, event: &ThemeEvent) -> crate::handlers::HandlerOutcome {
         // Update current theme
         if let Ok(mut current) = self.current_theme.write() {
-            *current = theme;
+            *current = event.theme;
         }
-        // Broadcast to all connected clients
-        let _ = self.sender.send(theme);
+        // Broadcast to all connected clients; having none is fine
+        let _ = self.sender.send(event.clone());
+        crate::handlers::HandlerOutcome::Success
     }
 }
\ No newline at end of file
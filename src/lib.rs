@@ -1,5 +1,6 @@
 #![allow(unexpected_cfgs)]
 
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt;
 use std::sync::Arc;
@@ -7,15 +8,21 @@ use std::sync::Arc;
 pub mod app;
 pub mod async_runtime;
 pub mod cli;
-pub mod config;
+pub mod commands;
 pub mod config_file;
 pub mod error;
+pub mod events;
 pub mod handlers;
 pub mod ipc;
+pub mod logging;
 pub mod lua_handler;
+pub mod monitors;
 pub mod platform;
+pub mod state;
+pub mod worker;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Theme {
     Light,
     Dark,
@@ -30,50 +37,67 @@ impl fmt::Display for Theme {
     }
 }
 
+impl std::str::FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "light" => Ok(Theme::Light),
+            "dark" => Ok(Theme::Dark),
+            other => Err(format!("Unknown theme: {:?}", other)),
+        }
+    }
+}
+
+pub use events::ThemeEvent;
+
 pub trait ThemeMonitor {
     fn start(&self) -> Result<(), Box<dyn Error>>;
     fn stop(&self) -> Result<(), Box<dyn Error>>;
     fn get_current_theme(&self) -> Theme;
 }
 
-pub fn run() -> Result<(), Box<dyn Error>> {
+/// Runs the command line; the error's exit code is what the process should
+/// exit with
+pub fn run() -> error::Result<()> {
     use clap::Parser;
     use cli::Args;
     use handlers::{CompositeThemeHandler, LoggingThemeHandler, ScriptHandler};
+    use ipc::{IpcHandler, IpcServer};
     use lua_handler::LuaHandler;
+    use logging::{Level, Logger};
     use config_file::Config;
+    use error::ThemeSwitcherError;
     
     let args = Args::parse();
     
     // Load config file early to check for IPC setting
-    let config = if let Some(ref config_path) = args.config {
-        config::log_info(&format!("Loading config from: {:?}", config_path));
-        Some(Config::load_from_file(config_path)?)
-    } else {
-        // Try to load from default location
-        let config_path = dirs::home_dir()
-            .map(|home| home.join(".config/theme-switcher/config.toml"))
-            .unwrap_or_default();
-        
-        if config_path.exists() {
-            config::log_info(&format!("Loading config from: {:?}", config_path));
-            Config::load_from_file(&config_path).ok()
-        } else {
-            None
-        }
-    };
+    let config = Config::load(args.config.as_ref(), args.strict)?;
     
-    // Use async runtime if IPC is enabled (command line takes precedence)
-    let ipc_enabled = args.ipc || config.as_ref().map(|c| c.general.ipc).unwrap_or(false);
-    if ipc_enabled {
-        config::log_info("IPC is enabled, starting with async runtime");
-        return async_runtime::run_with_tokio(args);
+    // Subcommands run once and exit instead of starting the daemon
+    if let Some(ref command) = args.command {
+        return commands::run(command, config.as_ref());
     }
     
-    // Set quiet mode globally (command line takes precedence)
+    // Set up logging (command line takes precedence)
     let quiet = args.quiet || config.as_ref().map(|c| c.general.quiet).unwrap_or(false);
-    config::set_quiet_mode(quiet);
+    let general = config.as_ref().map(|c| c.general.clone()).unwrap_or_default();
+    let level = match args.verbose {
+        0 => general.log_level,
+        1 => Level::Debug,
+        _ => Level::Trace,
+    };
+    let mut logger = Logger::new(level).with_quiet(quiet).with_format(general.log_format);
+    if let Some(ref log_file) = general.log_file {
+        logger = logger
+            .with_file(log_file, general.log_max_size, general.log_max_files)
+            .map_err(|e| ThemeSwitcherError::config(format!("cannot open log_file {:?}: {}", log_file, e)))?;
+    }
+    logger.install();
     
+    let runtime = tokio::runtime::Runtime::new()
+        .map_err(|e| ThemeSwitcherError::PlatformError(format!("Failed to start the async runtime: {}", e)))?;
+    let (sender, events) = events::channel();
     let mut composite = CompositeThemeHandler::new();
     
     // Add logging handler unless in quiet mode
@@ -81,6 +105,23 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         composite.add_handler(Arc::new(LoggingThemeHandler));
     }
     
+    // Setup IPC if requested (command line takes precedence over config)
+    let ipc_enabled = args.ipc || config.as_ref().map(|c| c.general.ipc).unwrap_or(false);
+    let ipc_server = if ipc_enabled {
+        let server = IpcServer::new()?.with_events(sender.clone());
+        runtime.block_on(server.start())?;
+        
+        // Add IPC handler to composite
+        composite.add_handler(Arc::new(IpcHandler::new(
+            server.get_broadcaster(),
+            server.get_current_theme_state()
+        )));
+        
+        Some(server)
+    } else {
+        None
+    };
+    
     // Create script handler combining CLI args and config
     let mut script_handler = ScriptHandler::new();
     let mut has_scripts = false;
@@ -116,6 +157,13 @@ pub fn run() -> Result<(), Box<dyn Error>> {
     }
     
     if has_scripts {
+        if let Some(ref cfg) = config {
+            script_handler = script_handler.with_max_parallel(cfg.scripts.max_parallel);
+            script_handler = script_handler.with_output_limit(cfg.scripts.output_limit);
+            if let Some(timeout) = cfg.scripts.timeout {
+                script_handler = script_handler.with_timeout(timeout);
+            }
+        }
         composite.add_handler(Arc::new(script_handler));
     }
     
@@ -157,8 +205,8 @@ pub fn run() -> Result<(), Box<dyn Error>> {
         composite.add_handler(Arc::new(lua_handler));
     }
     
-    let app = platform::create_application(Arc::new(composite))?;
-    app.run()?;
-
-    Ok(())
+    // Apply the current theme at startup (command line takes precedence)
+    let apply_on_start = args.apply_on_start || config.as_ref().map(|c| c.general.apply_on_start).unwrap_or(false);
+    
+    async_runtime::run(runtime, sender, events, Arc::new(composite), ipc_server, config.as_ref(), apply_on_start)
 }
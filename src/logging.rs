@@ -0,0 +1,309 @@
+use chrono::{Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Used until a logger is installed, e.g. while the config is loaded
+static DEFAULT_LOGGER: Logger = Logger::new(Level::Info);
+
+/// How severe a log message is, from most to least
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Error,
+    Warn,
+    #[default]
+    Info,
+    Debug,
+    Trace,
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        f.write_str(name)
+    }
+}
+
+/// How log lines are written
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Bare messages on the terminal, timestamped lines in the log file
+    #[default]
+    Text,
+    /// One JSON object per line with `timestamp`, `level` and `message`
+    Json,
+}
+
+/// Writes messages up to a level to the terminal and optionally a log file.
+///
+/// Errors and warnings go to stderr, everything else to stdout.
+pub struct Logger {
+    level: Level,
+    quiet: bool,
+    format: LogFormat,
+    file: Option<Mutex<LogFile>>,
+}
+
+impl Logger {
+    pub const fn new(level: Level) -> Self {
+        Self {
+            level,
+            quiet: false,
+            format: LogFormat::Text,
+            file: None,
+        }
+    }
+
+    /// Only shows errors and warnings on the terminal; the log file still
+    /// gets every message up to the level
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    pub fn with_format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Also appends to `path`. Once it would grow past `max_size` bytes it
+    /// is rotated to `path.1`, keeping up to `max_files` rotated files.
+    pub fn with_file(mut self, path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        self.file = Some(Mutex::new(LogFile::open(path, max_size, max_files)?));
+        Ok(self)
+    }
+
+    /// Makes this the logger for the rest of the process. Only the first
+    /// call has an effect.
+    pub fn install(self) {
+        let _ = LOGGER.set(self);
+    }
+
+    pub fn log(&self, level: Level, message: &str) {
+        if level > self.level {
+            return;
+        }
+
+        if !self.quiet || level <= Level::Warn {
+            let line = match self.format {
+                LogFormat::Text => message.to_string(),
+                LogFormat::Json => json_line(level, message),
+            };
+            // Nowhere left to report a closed terminal, so ignore it
+            let _ = if level <= Level::Warn {
+                writeln!(io::stderr().lock(), "{}", line)
+            } else {
+                writeln!(io::stdout().lock(), "{}", line)
+            };
+        }
+
+        if let Some(ref file) = self.file {
+            let line = match self.format {
+                LogFormat::Text => format!("{} {:<5} {}", timestamp(), level.to_string().to_uppercase(), message),
+                LogFormat::Json => json_line(level, message),
+            };
+            file.lock().unwrap().write(&line);
+        }
+    }
+}
+
+fn timestamp() -> String {
+    Local::now().to_rfc3339_opts(SecondsFormat::Millis, false)
+}
+
+fn json_line(level: Level, message: &str) -> String {
+    serde_json::json!({
+        "timestamp": timestamp(),
+        "level": level,
+        "message": message,
+    })
+    .to_string()
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: usize,
+}
+
+impl LogFile {
+    fn open(path: &Path, max_size: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn write(&mut self, line: &str) {
+        let len = line.len() as u64 + 1;
+        let full = self.max_size > 0 && self.size > 0 && self.size + len > self.max_size;
+        if full && let Err(e) = self.rotate() {
+            eprintln!("Failed to rotate log file {:?}: {}", self.path, e);
+        }
+
+        if writeln!(self.file, "{}", line).is_ok() {
+            self.size += len;
+        }
+    }
+
+    /// Shifts `log.1` to `log.2` and so on, dropping the oldest, then moves
+    /// the current file to `log.1` and starts a new one
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            for index in (1..self.max_files).rev() {
+                let from = rotated_path(&self.path, index);
+                if from.exists() {
+                    fs::rename(from, rotated_path(&self.path, index + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+/// `path` with `.<index>` appended, e.g. `theme-switcher.log.1`
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+pub fn log(level: Level, message: &str) {
+    LOGGER.get().unwrap_or(&DEFAULT_LOGGER).log(level, message);
+}
+
+pub fn log_error(message: &str) {
+    log(Level::Error, message);
+}
+
+pub fn log_warn(message: &str) {
+    log(Level::Warn, message);
+}
+
+pub fn log_info(message: &str) {
+    log(Level::Info, message);
+}
+
+pub fn log_debug(message: &str) {
+    log(Level::Debug, message);
+}
+
+pub fn log_trace(message: &str) {
+    log(Level::Trace, message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory for one test
+    fn temp_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("theme-switcher-log-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn rotation_shifts_files_and_drops_the_oldest() {
+        let dir = temp_dir("rotate");
+        let path = dir.join("theme-switcher.log");
+        // Each line takes six bytes, so every file holds one
+        let mut file = LogFile::open(&path, 10, 2).unwrap();
+        for n in 1..=5 {
+            file.write(&format!("line{}", n));
+        }
+
+        assert_eq!(read(&path), "line5\n");
+        assert_eq!(read(&rotated_path(&path, 1)), "line4\n");
+        assert_eq!(read(&rotated_path(&path, 2)), "line3\n");
+        assert!(!rotated_path(&path, 3).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_without_kept_files_starts_over() {
+        let dir = temp_dir("truncate");
+        let path = dir.join("theme-switcher.log");
+        let mut file = LogFile::open(&path, 10, 0).unwrap();
+        file.write("line1");
+        file.write("line2");
+
+        assert_eq!(read(&path), "line2\n");
+        assert!(!rotated_path(&path, 1).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_gets_json_lines_up_to_the_level() {
+        let dir = temp_dir("json");
+        let path = dir.join("theme-switcher.log");
+        let logger = Logger::new(Level::Info)
+            .with_quiet(true)
+            .with_format(LogFormat::Json)
+            .with_file(&path, 0, 0)
+            .unwrap();
+        logger.log(Level::Debug, "hidden");
+        logger.log(Level::Info, "applied dark");
+        logger.log(Level::Error, "script failed");
+
+        let lines: Vec<serde_json::Value> = read(&path)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0]["level"].as_str(), lines[0]["message"].as_str()), (Some("info"), Some("applied dark")));
+        assert_eq!((lines[1]["level"].as_str(), lines[1]["message"].as_str()), (Some("error"), Some("script failed")));
+        assert!(lines[0]["timestamp"].as_str().is_some_and(|timestamp| chrono::DateTime::parse_from_rfc3339(timestamp).is_ok()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn file_gets_timestamped_text_lines() {
+        let dir = temp_dir("text");
+        let path = dir.join("theme-switcher.log");
+        let logger = Logger::new(Level::Debug).with_quiet(true).with_file(&path, 0, 0).unwrap();
+        logger.log(Level::Trace, "hidden");
+        logger.log(Level::Debug, "probing");
+
+        let content = read(&path);
+        let (timestamp, rest) = content.trim_end().split_once(' ').unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(timestamp).is_ok(), "{}", content);
+        assert_eq!(rest, "DEBUG probing");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,10 @@
-use crate::{Theme, handlers::ThemeHandler};
-use mlua::{Lua, Result as LuaResult};
+use crate::{Theme, ThemeEvent, handlers::{HandlerOutcome, ThemeHandler}, worker::Cancellation};
+use crate::handlers::{OUTPUT_GRACE, ScriptExit, read_all, wait_for_script};
+use mlua::{HookTriggers, Lua, Result as LuaResult};
 use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
 
 /// Handler that executes Lua scripts when theme changes
 pub struct LuaHandler {
@@ -18,43 +22,33 @@ impl LuaHandler {
         })
     }
     
-    fn create_lua_context(&self) -> LuaResult<Lua> {
+    fn create_lua_context(&self, cancel: &Cancellation) -> LuaResult<Lua> {
         let lua = Lua::new();
         
         // Create theme-switcher module
         let theme_switcher = lua.create_table()?;
         
         // Add utility functions
-        theme_switcher.set("execute", lua.create_function(|lua_ctx, cmd: String| {
-            use std::process::Command;
-            let output = Command::new("sh")
-                .arg("-c")
-                .arg(&cmd)
-                .output()
-                .map_err(|e| mlua::Error::external(e))?;
-            
-            let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-            let success = output.status.success();
-            
-            let result = lua_ctx.create_table()?;
-            result.set("stdout", stdout)?;
-            result.set("stderr", stderr)?;
-            result.set("success", success)?;
-            
-            Ok(result)
+        let cancel = cancel.clone();
+        theme_switcher.set("execute", lua.create_function(move |lua_ctx, (cmd, timeout): (String, Option<f64>)| {
+            let timeout = timeout
+                .map(Duration::try_from_secs_f64)
+                .transpose()
+                .map_err(mlua::Error::external)?;
+            execute(lua_ctx, &cmd, timeout, &cancel)
         })?)?;
         
         // Add logging function
         theme_switcher.set("log", lua.create_function(|_, msg: String| {
-            use crate::config::log_info;
+            use crate::logging::log_info;
             log_info(&msg);
             Ok(())
         })?)?;
         
         // Add error logging function
         theme_switcher.set("log_error", lua.create_function(|_, msg: String| {
-            eprintln!("{}", msg);
+            use crate::logging::log_error;
+            log_error(&msg);
             Ok(())
         })?)?;
         
@@ -94,39 +88,58 @@ impl LuaHandler {
         self
     }
     
-    fn execute_script(&self, script_path: &PathBuf, theme: Theme) {
-        use crate::config::log_info;
+    fn execute_script(&self, script_path: &PathBuf, event: &ThemeEvent, cancel: &Cancellation) -> HandlerOutcome {
+        use crate::logging::{log_error, log_info};
+        
+        if cancel.is_cancelled() {
+            return HandlerOutcome::Cancelled;
+        }
         
         log_info(&format!("Executing Lua script: {:?}", script_path));
         
+        match self.run_script(script_path, event, cancel) {
+            Ok(()) => HandlerOutcome::Success,
+            Err(_) if cancel.is_cancelled() => {
+                log_info(&format!("Aborted superseded Lua script: {:?}", script_path));
+                HandlerOutcome::Cancelled
+            }
+            Err(error) => {
+                log_error(&error);
+                HandlerOutcome::Failed { error }
+            }
+        }
+    }
+    
+    fn run_script(&self, script_path: &PathBuf, event: &ThemeEvent, cancel: &Cancellation) -> Result<(), String> {
         // Create new Lua context for each execution
-        let lua = match self.create_lua_context() {
-            Ok(lua) => lua,
-            Err(e) => {
-                eprintln!("Failed to create Lua context: {}", e);
-                return;
+        let lua = self
+            .create_lua_context(cancel)
+            .map_err(|e| format!("Failed to create Lua context: {}", e))?;
+        
+        // Abort the script once a newer theme change supersedes it
+        let cancel = cancel.clone();
+        lua.set_hook(HookTriggers::new().every_nth_instruction(1000), move |_, _| {
+            if cancel.is_cancelled() {
+                return Err(mlua::Error::runtime("cancelled by a newer theme change"));
             }
-        };
+            Ok(())
+        });
         
         // Set current theme in Lua globals
-        if let Err(e) = self.set_theme_info(&lua, theme) {
-            eprintln!("Failed to set theme info: {}", e);
-            return;
-        }
+        self.set_theme_info(&lua, event)
+            .map_err(|e| format!("Failed to set theme info: {}", e))?;
         
         // Read and execute the script
-        match std::fs::read_to_string(script_path) {
-            Ok(script_content) => {
-                if let Err(e) = lua.load(&script_content).exec() {
-                    eprintln!("Lua script error: {}", e);
-                }
-            }
-            Err(e) => eprintln!("Failed to read Lua script: {}", e),
-        }
+        let script_content = std::fs::read_to_string(script_path)
+            .map_err(|e| format!("Failed to read Lua script: {}", e))?;
+        lua.load(&script_content)
+            .exec()
+            .map_err(|e| format!("Lua script error: {}", e))
     }
     
-    fn set_theme_info(&self, lua: &Lua, theme: Theme) -> LuaResult<()> {
+    fn set_theme_info(&self, lua: &Lua, event: &ThemeEvent) -> LuaResult<()> {
         let globals = lua.globals();
+        let theme = event.theme;
         
         // Set individual globals for convenience
         globals.set("THEME", theme.to_string())?;
@@ -140,10 +153,61 @@ impl LuaHandler {
         theme_switcher.set("is_dark", matches!(theme, Theme::Dark))?;
         theme_switcher.set("is_light", matches!(theme, Theme::Light))?;
         
+        // Full event details; `previous` is nil when unknown
+        let event_table = lua.create_table()?;
+        event_table.set("theme", theme.to_string())?;
+        event_table.set("previous", event.previous.map(|previous| previous.to_string()))?;
+        event_table.set("source", event.source.to_string())?;
+        event_table.set("timestamp", event.timestamp.to_rfc3339())?;
+        event_table.set("unix_time", event.timestamp.timestamp())?;
+        globals.set("EVENT", event_table.clone())?;
+        theme_switcher.set("event", event_table)?;
+        
         Ok(())
     }
 }
 
+/// Runs `cmd` with `sh -c` for `theme_switcher.execute`. It runs in its own
+/// process group, which is terminated once `timeout` passes or a newer theme
+/// change supersedes the script.
+fn execute<'lua>(lua: &'lua Lua, cmd: &str, timeout: Option<Duration>, cancel: &Cancellation) -> LuaResult<mlua::Table<'lua>> {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(cmd)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    
+    let mut child = command.spawn().map_err(mlua::Error::external)?;
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+    
+    let (success, timed_out) = match wait_for_script(&mut child, timeout, cancel).map_err(mlua::Error::external)? {
+        ScriptExit::Exited(status) => (status.success(), false),
+        ScriptExit::Cancelled => return Err(mlua::Error::runtime("cancelled by a newer theme change")),
+        ScriptExit::TimedOut(_) => (false, true),
+    };
+    
+    // Processes left running in the background may hold the pipes open
+    let deadline = Instant::now() + OUTPUT_GRACE;
+    let collect = |output: mpsc::Receiver<Vec<u8>>| {
+        let bytes = output
+            .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+            .unwrap_or_default();
+        String::from_utf8_lossy(&bytes).into_owned()
+    };
+    
+    let result = lua.create_table()?;
+    result.set("stdout", collect(stdout))?;
+    result.set("stderr", collect(stderr))?;
+    result.set("success", success)?;
+    result.set("timed_out", timed_out)?;
+    Ok(result)
+}
+
 impl Default for LuaHandler {
     fn default() -> Self {
         Self::new().expect("Failed to create Lua handler")
@@ -151,24 +215,86 @@ impl Default for LuaHandler {
 }
 
 impl ThemeHandler for LuaHandler {
-    fn on_theme_change(&self, theme: Theme) {
-        // Execute theme-specific scripts
-        match theme {
-            Theme::Light => {
-                for script in &self.light_scripts {
-                    self.execute_script(script, theme);
-                }
-            }
-            Theme::Dark => {
-                for script in &self.dark_scripts {
-                    self.execute_script(script, theme);
-                }
+    fn name(&self) -> &str {
+        "lua"
+    }
+    
+    fn on_theme_change(&self, event: &ThemeEvent) -> HandlerOutcome {
+        self.on_theme_change_cancellable(event, &Cancellation::new())
+    }
+    
+    fn on_theme_change_cancellable(&self, event: &ThemeEvent, cancel: &Cancellation) -> HandlerOutcome {
+        // Theme-specific scripts run first, then any-change scripts
+        let scripts = match event.theme {
+            Theme::Light => &self.light_scripts,
+            Theme::Dark => &self.dark_scripts,
+        };
+        
+        // Stop at the first cancelled script; the rest would be cancelled too
+        let mut outcomes = Vec::new();
+        for script in scripts.iter().chain(&self.any_change_scripts) {
+            let outcome = self.execute_script(script, event, cancel);
+            let cancelled = outcome == HandlerOutcome::Cancelled;
+            outcomes.push((script.display().to_string(), outcome));
+            if cancelled {
+                break;
             }
         }
+        HandlerOutcome::combine(outcomes)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::ThemeSource;
+    
+    /// A handler running `source` as its dark script
+    fn handler(name: &str, source: &str) -> LuaHandler {
+        let path = std::env::temp_dir().join(format!("theme-switcher-{}-{}.lua", name, std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        LuaHandler::new().unwrap().with_dark_script(path)
+    }
+    
+    fn dark() -> ThemeEvent {
+        ThemeEvent::new(Theme::Dark, ThemeSource::Manual)
+    }
+    
+    #[test]
+    fn execute_returns_output() {
+        let handler = handler(
+            "output",
+            r#"local result = theme_switcher.execute("echo $0")
+            assert(result.success and not result.timed_out)
+            assert(result.stdout == "sh\n")"#,
+        );
+        assert_eq!(handler.on_theme_change(&dark()), HandlerOutcome::Success);
+    }
+    
+    #[test]
+    fn execute_times_out() {
+        let handler = handler(
+            "timeout",
+            r#"local result = theme_switcher.execute("sleep 30", 0.1)
+            assert(result.timed_out and not result.success)"#,
+        );
+        let started = Instant::now();
+        assert_eq!(handler.on_theme_change(&dark()), HandlerOutcome::Success);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+    
+    #[test]
+    fn superseded_execute_is_terminated() {
+        let handler = handler("cancel", r#"theme_switcher.execute("sleep 30")"#);
+        let cancel = Cancellation::new();
+        let canceller = cancel.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            canceller.cancel();
+        });
         
-        // Execute any-change scripts
-        for script in &self.any_change_scripts {
-            self.execute_script(script, theme);
-        }
+        let started = Instant::now();
+        let outcome = handler.on_theme_change_cancellable(&dark(), &cancel);
+        assert_eq!(outcome, HandlerOutcome::Cancelled);
+        assert!(started.elapsed() < Duration::from_secs(10));
     }
-}
\ No newline at end of file
+}
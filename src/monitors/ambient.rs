@@ -0,0 +1,357 @@
+use super::stop::StopSignal;
+use crate::config_file::AmbientConfig;
+use crate::logging::log_error;
+use crate::{Theme, ThemeMonitor};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Light sensor exposed through the Linux Industrial I/O subsystem
+#[derive(Debug, Clone)]
+pub struct IlluminanceSensor {
+    device: PathBuf,
+}
+
+impl IlluminanceSensor {
+    pub fn new(device: PathBuf) -> Self {
+        Self { device }
+    }
+
+    /// Finds the first IIO device under `root` that reports illuminance
+    pub fn discover(root: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut devices: Vec<PathBuf> = std::fs::read_dir(root)
+            .map_err(|e| format!("Unable to read {:?}: {}", root, e))?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|device| {
+                device.join("in_illuminance_raw").exists() || device.join("in_illuminance_input").exists()
+            })
+            .collect();
+        devices.sort();
+
+        devices
+            .into_iter()
+            .next()
+            .map(Self::new)
+            .ok_or_else(|| format!("No illuminance sensor found under {:?}", root).into())
+    }
+
+    pub fn device(&self) -> &Path {
+        &self.device
+    }
+
+    /// Reads the current illuminance in lux.
+    ///
+    /// Drivers either expose a processed `in_illuminance_input`, or a raw
+    /// reading with optional `_offset` and `_scale` attributes.
+    pub fn read_lux(&self) -> Result<f64, Box<dyn Error>> {
+        if let Some(lux) = self.read_attribute("in_illuminance_input")? {
+            return Ok(lux);
+        }
+
+        let raw = self
+            .read_attribute("in_illuminance_raw")?
+            .ok_or_else(|| format!("{:?} has no illuminance reading", self.device))?;
+        let offset = self.read_attribute("in_illuminance_offset")?.unwrap_or(0.0);
+        let scale = self.read_attribute("in_illuminance_scale")?.unwrap_or(1.0);
+
+        Ok((raw + offset) * scale)
+    }
+
+    fn read_attribute(&self, name: &str) -> Result<Option<f64>, Box<dyn Error>> {
+        let path = self.device.join(name);
+        match std::fs::read_to_string(&path) {
+            Ok(value) => value
+                .trim()
+                .parse()
+                .map(Some)
+                .map_err(|e| format!("Invalid value in {:?}: {}", path, e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("Unable to read {:?}: {}", path, e).into()),
+        }
+    }
+}
+
+/// Two-threshold switch with a minimum dwell time.
+///
+/// Readings below `dark_below` favour dark and readings above `light_above`
+/// favour light; anything in between keeps the current theme. A change only
+/// takes effect once the new side has been observed continuously for `dwell`.
+#[derive(Debug, Clone)]
+pub struct Hysteresis {
+    dark_below: f64,
+    light_above: f64,
+    dwell: Duration,
+    current: Theme,
+    pending_since: Option<Instant>,
+}
+
+impl Hysteresis {
+    pub fn new(dark_below: f64, light_above: f64, dwell: Duration, initial: Theme) -> Self {
+        Self {
+            dark_below,
+            light_above,
+            dwell,
+            current: initial,
+            pending_since: None,
+        }
+    }
+
+    /// Theme for a single reading without any history, used at startup
+    pub fn classify(dark_below: f64, light_above: f64, lux: f64) -> Theme {
+        if lux < (dark_below + light_above) / 2.0 {
+            Theme::Dark
+        } else {
+            Theme::Light
+        }
+    }
+
+    pub fn current(&self) -> Theme {
+        self.current
+    }
+
+    /// Feeds a reading taken at `now`, returning the new theme if it switched
+    pub fn update(&mut self, lux: f64, now: Instant) -> Option<Theme> {
+        let wanted = if lux < self.dark_below {
+            Theme::Dark
+        } else if lux > self.light_above {
+            Theme::Light
+        } else {
+            self.current
+        };
+
+        if wanted == self.current {
+            self.pending_since = None;
+            return None;
+        }
+
+        let since = *self.pending_since.get_or_insert(now);
+        if now.duration_since(since) >= self.dwell {
+            self.current = wanted;
+            self.pending_since = None;
+            Some(wanted)
+        } else {
+            None
+        }
+    }
+}
+
+/// Theme monitor that samples an ambient light sensor
+pub struct AmbientThemeMonitor {
+    sensor: IlluminanceSensor,
+    config: AmbientConfig,
+    callback: Arc<dyn Fn(Theme) + Send + Sync>,
+    /// The switch state while running, shared with the sampling thread
+    hysteresis: Arc<Mutex<Option<Hysteresis>>>,
+    worker: Mutex<Option<(StopSignal, JoinHandle<()>)>>,
+}
+
+impl AmbientThemeMonitor {
+    /// Creates a monitor for the configured sensor, discovering one if no
+    /// device is set
+    pub fn new<F>(config: AmbientConfig, callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        if config.dark_below > config.light_above {
+            return Err(format!(
+                "[ambient] dark_below ({}) must not exceed light_above ({})",
+                config.dark_below, config.light_above
+            )
+            .into());
+        }
+
+        let sensor = match config.device {
+            Some(ref device) => IlluminanceSensor::new(config.sysfs_root.join(device)),
+            None => IlluminanceSensor::discover(&config.sysfs_root)?,
+        };
+
+        Ok(Self {
+            sensor,
+            config,
+            callback: Arc::new(callback),
+            hysteresis: Arc::new(Mutex::new(None)),
+            worker: Mutex::new(None),
+        })
+    }
+
+    pub fn sensor(&self) -> &IlluminanceSensor {
+        &self.sensor
+    }
+
+    fn classify(&self, lux: f64) -> Theme {
+        Hysteresis::classify(self.config.dark_below, self.config.light_above, lux)
+    }
+}
+
+impl ThemeMonitor for AmbientThemeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        let mut worker = self.worker.lock().unwrap();
+        if worker.is_some() {
+            return Err("Monitor is already running".into());
+        }
+
+        let initial = self.classify(self.sensor.read_lux()?);
+        *self.hysteresis.lock().unwrap() = Some(Hysteresis::new(
+            self.config.dark_below,
+            self.config.light_above,
+            self.config.dwell,
+            initial,
+        ));
+
+        let hysteresis = Arc::clone(&self.hysteresis);
+        let sensor = self.sensor.clone();
+        let interval = self.config.interval;
+        let callback = Arc::clone(&self.callback);
+        let stop = StopSignal::new();
+        let thread_stop = stop.clone();
+
+        let thread = std::thread::Builder::new()
+            .name("ambient-monitor".to_string())
+            .spawn(move || {
+                while !thread_stop.wait(interval) {
+                    match sensor.read_lux() {
+                        Ok(lux) => {
+                            // Released before the callback, which may ask
+                            // for the current theme
+                            let switched = hysteresis
+                                .lock()
+                                .unwrap()
+                                .as_mut()
+                                .and_then(|hysteresis| hysteresis.update(lux, Instant::now()));
+                            if let Some(theme) = switched {
+                                callback(theme);
+                            }
+                        }
+                        Err(e) => log_error(&format!("Failed to read light sensor: {}", e)),
+                    }
+                }
+            })?;
+
+        *worker = Some((stop, thread));
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        if let Some((stop, thread)) = self.worker.lock().unwrap().take() {
+            stop.stop();
+            let _ = thread.join();
+        }
+        *self.hysteresis.lock().unwrap() = None;
+        Ok(())
+    }
+
+    /// The theme the monitor holds while running, which a reading between
+    /// the thresholds does not change. Before the first sample the reading
+    /// is classified on its own.
+    fn get_current_theme(&self) -> Theme {
+        if let Some(ref hysteresis) = *self.hysteresis.lock().unwrap() {
+            return hysteresis.current();
+        }
+        self.sensor
+            .read_lux()
+            .map(|lux| self.classify(lux))
+            .unwrap_or(Theme::Light)
+    }
+}
+
+impl Drop for AmbientThemeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DWELL: Duration = Duration::from_secs(10);
+
+    #[test]
+    fn hysteresis_keeps_theme_between_thresholds() {
+        let start = Instant::now();
+        let mut hysteresis = Hysteresis::new(50.0, 200.0, Duration::ZERO, Theme::Light);
+
+        assert_eq!(hysteresis.update(100.0, start), None);
+        assert_eq!(hysteresis.update(50.0, start), None);
+        assert_eq!(hysteresis.update(49.0, start), Some(Theme::Dark));
+        assert_eq!(hysteresis.update(200.0, start), None);
+        assert_eq!(hysteresis.current(), Theme::Dark);
+        assert_eq!(hysteresis.update(201.0, start), Some(Theme::Light));
+    }
+
+    #[test]
+    fn hysteresis_waits_for_dwell() {
+        let start = Instant::now();
+        let mut hysteresis = Hysteresis::new(50.0, 200.0, DWELL, Theme::Light);
+
+        assert_eq!(hysteresis.update(10.0, start), None);
+        assert_eq!(hysteresis.update(10.0, start + DWELL / 2), None);
+        assert_eq!(hysteresis.update(10.0, start + DWELL), Some(Theme::Dark));
+        assert_eq!(hysteresis.current(), Theme::Dark);
+    }
+
+    #[test]
+    fn hysteresis_restarts_dwell_when_reading_returns() {
+        let start = Instant::now();
+        let mut hysteresis = Hysteresis::new(50.0, 200.0, DWELL, Theme::Light);
+
+        assert_eq!(hysteresis.update(10.0, start), None);
+        assert_eq!(hysteresis.update(100.0, start + DWELL / 2), None);
+        assert_eq!(hysteresis.update(10.0, start + DWELL), None);
+        assert_eq!(hysteresis.update(10.0, start + DWELL * 2), Some(Theme::Dark));
+    }
+
+    #[test]
+    fn classify_splits_at_midpoint() {
+        assert_eq!(Hysteresis::classify(50.0, 200.0, 124.0), Theme::Dark);
+        assert_eq!(Hysteresis::classify(50.0, 200.0, 125.0), Theme::Light);
+    }
+
+    /// A sysfs root with one IIO device reporting `lux`
+    fn fake_sysfs(test: &str, lux: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("theme-switcher-sysfs-{}-{}", test, std::process::id()));
+        let device = root.join("iio:device0");
+        std::fs::create_dir_all(&device).unwrap();
+        std::fs::write(device.join("in_illuminance_raw"), lux).unwrap();
+        std::fs::write(device.join("in_illuminance_scale"), "0.5\n").unwrap();
+        root
+    }
+
+    fn config(sysfs_root: PathBuf) -> AmbientConfig {
+        AmbientConfig {
+            sysfs_root,
+            dark_below: 50.0,
+            light_above: 200.0,
+            interval: Duration::from_millis(10),
+            dwell: Duration::ZERO,
+            ..AmbientConfig::default()
+        }
+    }
+
+    #[test]
+    fn discovers_and_scales_sensor() {
+        let root = fake_sysfs("discover", "300\n");
+        let sensor = IlluminanceSensor::discover(&root).unwrap();
+        assert_eq!(sensor.device(), root.join("iio:device0"));
+        assert_eq!(sensor.read_lux().unwrap(), 150.0);
+    }
+
+    #[test]
+    fn current_theme_follows_hysteresis_while_running() {
+        let root = fake_sysfs("current", "40\n");
+        let raw = root.join("iio:device0/in_illuminance_raw");
+        let monitor = AmbientThemeMonitor::new(config(root), |_| {}).unwrap();
+        assert_eq!(monitor.get_current_theme(), Theme::Dark);
+
+        monitor.start().unwrap();
+        // 150 lux is between the thresholds but above the midpoint
+        std::fs::write(&raw, "300\n").unwrap();
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(monitor.get_current_theme(), Theme::Dark);
+
+        monitor.stop().unwrap();
+        assert_eq!(monitor.get_current_theme(), Theme::Light);
+    }
+}
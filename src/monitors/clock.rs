@@ -0,0 +1,129 @@
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::time::Duration;
+
+/// Source of local wall-clock time for time-driven monitors.
+///
+/// Schedules are expressed in local time, so the clock also converts a local
+/// target back into real elapsed time; that is where DST shifts are handled.
+/// Tests can substitute a fixed clock to drive transitions deterministically.
+pub trait Clock: Send + Sync {
+    /// Current local wall-clock time
+    fn now(&self) -> NaiveDateTime;
+
+    /// Real time remaining until the given local wall-clock time
+    fn until(&self, target: NaiveDateTime) -> Duration;
+}
+
+/// Clock backed by the system time and local timezone
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+
+    fn until(&self, target: NaiveDateTime) -> Duration {
+        until_in(&Local, &Local::now(), target)
+    }
+}
+
+/// Real time from `now` until the local time `target` in `tz`
+fn until_in<Tz: TimeZone>(tz: &Tz, now: &DateTime<Tz>, target: NaiveDateTime) -> Duration {
+    // A wall-clock time skipped by a DST change happens "an hour later"
+    let target = tz
+        .from_local_datetime(&target)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(target + chrono::Duration::hours(1))).earliest());
+
+    target
+        .and_then(|target| target.signed_duration_since(now).to_std().ok())
+        .unwrap_or(Duration::ZERO)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, MappedLocalTime, NaiveDate};
+
+    /// Central European Time in 2024: clocks go from 02:00 to 03:00 on
+    /// March 31 and from 03:00 back to 02:00 on October 27, both at 01:00 UTC
+    #[derive(Debug, Clone, Copy)]
+    struct Cet2024;
+
+    fn at(month: u32, day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, month, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    impl TimeZone for Cet2024 {
+        type Offset = FixedOffset;
+
+        fn from_offset(_: &FixedOffset) -> Self {
+            Cet2024
+        }
+
+        fn offset_from_local_date(&self, local: &NaiveDate) -> MappedLocalTime<FixedOffset> {
+            self.offset_from_local_datetime(&local.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_local_datetime(&self, local: &NaiveDateTime) -> MappedLocalTime<FixedOffset> {
+            let matching: Vec<FixedOffset> = [2, 1]
+                .into_iter()
+                .map(|hours| FixedOffset::east_opt(hours * 3600).unwrap())
+                .filter(|offset| self.offset_from_utc_datetime(&(*local - *offset)) == *offset)
+                .collect();
+            match matching[..] {
+                [] => MappedLocalTime::None,
+                [offset] => MappedLocalTime::Single(offset),
+                [earlier, later] => MappedLocalTime::Ambiguous(earlier, later),
+                _ => unreachable!(),
+            }
+        }
+
+        fn offset_from_utc_date(&self, utc: &NaiveDate) -> FixedOffset {
+            self.offset_from_utc_datetime(&utc.and_hms_opt(0, 0, 0).unwrap())
+        }
+
+        fn offset_from_utc_datetime(&self, utc: &NaiveDateTime) -> FixedOffset {
+            let summer = (at(3, 31, 1, 0)..at(10, 27, 1, 0)).contains(utc);
+            FixedOffset::east_opt(if summer { 2 } else { 1 } * 3600).unwrap()
+        }
+    }
+
+    fn until(now: NaiveDateTime, target: NaiveDateTime) -> Duration {
+        let now = Cet2024.from_local_datetime(&now).earliest().unwrap();
+        until_in(&Cet2024, &now, target)
+    }
+
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    #[test]
+    fn counts_real_time_across_spring_forward() {
+        assert_eq!(until(at(3, 31, 1, 0), at(3, 31, 4, 0)), 2 * HOUR);
+    }
+
+    #[test]
+    fn counts_real_time_across_fall_back() {
+        assert_eq!(until(at(10, 27, 1, 0), at(10, 27, 4, 0)), 4 * HOUR);
+    }
+
+    #[test]
+    fn moves_skipped_time_an_hour_later() {
+        // 02:30 does not exist; 03:30 summer time is 01:30 UTC
+        assert_eq!(until(at(3, 31, 1, 0), at(3, 31, 2, 30)), HOUR + HOUR / 2);
+    }
+
+    #[test]
+    fn uses_first_of_repeated_times() {
+        // 02:30 happens twice; the first is 00:30 UTC
+        assert_eq!(until(at(10, 27, 1, 0), at(10, 27, 2, 30)), HOUR + HOUR / 2);
+    }
+
+    #[test]
+    fn past_target_is_due_now() {
+        assert_eq!(until(at(6, 1, 12, 0), at(6, 1, 11, 0)), Duration::ZERO);
+    }
+}
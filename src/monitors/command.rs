@@ -0,0 +1,301 @@
+use crate::config_file::{CommandConfig, CommandRule, format_duration};
+use crate::handlers::{OUTPUT_GRACE, ScriptExit, read_all, wait_for_script};
+use crate::logging::log_error;
+use crate::worker::Cancellation;
+use crate::{Theme, ThemeMonitor};
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+impl CommandRule {
+    /// Returns true if every condition set on the rule holds for the output
+    pub fn matches(&self, output: &Output) -> bool {
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let stdout = stdout.trim();
+
+        self.exit_code.is_none_or(|code| output.status.code() == Some(code))
+            && self.stdout.as_deref().is_none_or(|expected| stdout == expected)
+            && self
+                .stdout_contains
+                .as_deref()
+                .is_none_or(|needle| stdout.to_lowercase().contains(&needle.to_lowercase()))
+    }
+}
+
+/// Maps probe output to a theme. The first matching rule wins; without
+/// rules, stdout itself must read `light` or `dark`.
+pub fn evaluate(rules: &[CommandRule], output: &Output) -> Result<Theme, Box<dyn Error>> {
+    if rules.is_empty() {
+        return Ok(String::from_utf8_lossy(&output.stdout).parse::<Theme>()?);
+    }
+
+    rules
+        .iter()
+        .find(|rule| rule.matches(output))
+        .map(|rule| rule.theme)
+        .ok_or_else(|| {
+            format!(
+                "No rule matched probe output (status: {}, stdout: {:?})",
+                output.status,
+                String::from_utf8_lossy(&output.stdout).trim()
+            )
+            .into()
+        })
+}
+
+/// Runs `probe` with `sh -c` in its own process group, which is terminated
+/// once `timeout` passes or `cancel` is triggered. A zero timeout never
+/// expires.
+fn run_probe(probe: &str, rules: &[CommandRule], timeout: Duration, cancel: &Cancellation) -> Result<Theme, Box<dyn Error>> {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(probe)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+
+    let mut child = command.spawn()?;
+    let stdout = read_all(child.stdout.take());
+
+    let timeout = Some(timeout).filter(|timeout| !timeout.is_zero());
+    let status = match wait_for_script(&mut child, timeout, cancel)? {
+        ScriptExit::Exited(status) => status,
+        ScriptExit::Cancelled => return Err("Probe was cancelled".into()),
+        ScriptExit::TimedOut(timeout) => {
+            return Err(format!("Probe timed out after {}", format_duration(timeout)).into());
+        }
+    };
+
+    // Processes left running in the background may hold stdout open
+    let output = Output {
+        status,
+        stdout: stdout.recv_timeout(OUTPUT_GRACE).unwrap_or_default(),
+        stderr: Vec::new(),
+    };
+    evaluate(rules, &output)
+}
+
+enum Wake {
+    Trigger,
+    Stop,
+}
+
+struct Worker {
+    wake: Sender<Wake>,
+    /// Terminates the probe in flight when the monitor stops
+    cancel: Cancellation,
+    trigger: Option<Child>,
+}
+
+/// Theme monitor that runs a probe command and maps its result to a theme.
+///
+/// The probe runs every `interval`, and additionally whenever the optional
+/// `trigger` command prints a line (e.g. `gsettings monitor ...`).
+pub struct CommandThemeMonitor {
+    probe: String,
+    config: CommandConfig,
+    callback: Arc<dyn Fn(Theme) + Send + Sync>,
+    worker: Mutex<Option<Worker>>,
+}
+
+impl CommandThemeMonitor {
+    pub fn new<F>(config: CommandConfig, callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        let probe = config.probe.clone().ok_or("[command] probe must be set")?;
+        if config.interval.is_zero() && config.trigger.is_none() {
+            return Err("[command] needs a non-zero interval or a trigger".into());
+        }
+
+        Ok(Self {
+            probe,
+            config,
+            callback: Arc::new(callback),
+            worker: Mutex::new(None),
+        })
+    }
+
+    /// Runs the probe once
+    pub fn probe(&self) -> Result<Theme, Box<dyn Error>> {
+        run_probe(&self.probe, &self.config.rules, self.config.timeout, &Cancellation::new())
+    }
+}
+
+impl ThemeMonitor for CommandThemeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        let mut worker = self.worker.lock().unwrap();
+        if worker.is_some() {
+            return Err("Monitor is already running".into());
+        }
+
+        let (wake, wake_rx) = mpsc::channel();
+
+        let trigger = match self.config.trigger {
+            Some(ref trigger) => {
+                let mut child = Command::new("sh")
+                    .arg("-c")
+                    .arg(trigger)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::piped())
+                    .spawn()?;
+                let stdout = child.stdout.take().expect("trigger stdout is piped");
+                // The reader exits once the trigger closes its output or the
+                // probe thread has gone away, so it is not joined
+                let wake = wake.clone();
+                std::thread::Builder::new()
+                    .name("command-trigger".to_string())
+                    .spawn(move || {
+                        for _ in BufReader::new(stdout).lines().map_while(Result::ok) {
+                            if wake.send(Wake::Trigger).is_err() {
+                                break;
+                            }
+                        }
+                    })?;
+                Some(child)
+            }
+            None => None,
+        };
+
+        let mut current = self.probe().ok();
+        let probe = self.probe.clone();
+        let rules = self.config.rules.clone();
+        let interval = self.config.interval;
+        let timeout = self.config.timeout;
+        let callback = Arc::clone(&self.callback);
+        let cancel = Cancellation::new();
+        let probe_cancel = cancel.clone();
+
+        std::thread::Builder::new()
+            .name("command-monitor".to_string())
+            .spawn(move || {
+                loop {
+                    let woken = if interval.is_zero() {
+                        wake_rx.recv().map_err(|_| RecvTimeoutError::Disconnected)
+                    } else {
+                        wake_rx.recv_timeout(interval)
+                    };
+
+                    match woken {
+                        Ok(Wake::Trigger) | Err(RecvTimeoutError::Timeout) => {}
+                        Ok(Wake::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                    }
+
+                    let result = run_probe(&probe, &rules, timeout, &probe_cancel);
+                    if probe_cancel.is_cancelled() {
+                        break;
+                    }
+                    match result {
+                        Ok(theme) if current != Some(theme) => {
+                            current = Some(theme);
+                            callback(theme);
+                        }
+                        Ok(_) => {}
+                        Err(e) => log_error(&format!("Theme probe failed: {}", e)),
+                    }
+                }
+            })?;
+
+        *worker = Some(Worker {
+            wake,
+            cancel,
+            trigger,
+        });
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            // The thread is not joined, as terminating a probe that ignores
+            // SIGTERM takes a while; it exits on its own once that is done
+            worker.cancel.cancel();
+            let _ = worker.wake.send(Wake::Stop);
+
+            if let Some(mut child) = worker.trigger {
+                let _ = child.kill();
+                let _ = child.wait();
+            }
+        }
+        Ok(())
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        self.probe().unwrap_or(Theme::Light)
+    }
+}
+
+impl Drop for CommandThemeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    fn config(probe: &str) -> CommandConfig {
+        CommandConfig {
+            probe: Some(probe.to_string()),
+            ..CommandConfig::default()
+        }
+    }
+
+    #[test]
+    fn probe_output_is_the_theme_without_rules() {
+        let monitor = CommandThemeMonitor::new(config("echo dark"), |_| {}).unwrap();
+        assert_eq!(monitor.probe().unwrap(), Theme::Dark);
+    }
+
+    #[test]
+    fn hung_probe_times_out() {
+        let monitor = CommandThemeMonitor::new(
+            CommandConfig {
+                timeout: Duration::from_millis(200),
+                ..config("sleep 30")
+            },
+            |_| {},
+        )
+        .unwrap();
+
+        let started = Instant::now();
+        let error = monitor.probe().unwrap_err().to_string();
+        assert!(error.contains("timed out"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn stop_does_not_wait_for_probe_in_flight() {
+        let dir = std::env::temp_dir().join(format!("theme-switcher-probe-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let hang = dir.join("hang");
+        let _ = std::fs::remove_file(&hang);
+
+        // Answers at once for start(), then hangs once `hang` exists
+        let probe = format!("test -e '{}' && sleep 30; echo light", hang.display());
+        let monitor = CommandThemeMonitor::new(
+            CommandConfig {
+                interval: Duration::from_millis(50),
+                timeout: Duration::from_secs(60),
+                ..config(&probe)
+            },
+            |_| {},
+        )
+        .unwrap();
+        monitor.start().unwrap();
+        std::fs::write(&hang, "").unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+
+        let started = Instant::now();
+        monitor.stop().unwrap();
+        assert!(started.elapsed() < Duration::from_secs(1));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,356 @@
+use crate::config_file::ArbitrationPolicy;
+use crate::events::{EventSender, ThemeEvent, ThemeSource};
+use crate::{Theme, ThemeMonitor};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+struct SourceState {
+    priority: i32,
+    only: Option<Theme>,
+    theme: Option<Theme>,
+    /// What triggered the source's last report
+    source: Option<ThemeSource>,
+    /// Sequence number of the source's last report, for `Latest`
+    updated: u64,
+}
+
+impl SourceState {
+    /// The source's vote, or None if it abstains. A source restricted to one
+    /// theme abstains while it reports the other.
+    fn opinion(&self) -> Option<Theme> {
+        match (self.theme, self.only) {
+            (Some(theme), Some(only)) if theme != only => None,
+            (theme, _) => theme,
+        }
+    }
+}
+
+/// Shared state that turns per-source reports into one arbitrated theme
+#[derive(Debug, Clone)]
+struct Arbiter {
+    policy: ArbitrationPolicy,
+    sources: Vec<SourceState>,
+    current: Option<Theme>,
+    sequence: u64,
+}
+
+impl Arbiter {
+    /// The arbitrated theme and the source it is attributed to
+    fn arbitrate(&self) -> Option<(Theme, &SourceState)> {
+        let voting = self.sources.iter().filter_map(|s| s.opinion().map(|theme| (theme, s)));
+
+        match self.policy {
+            ArbitrationPolicy::Priority => voting.max_by_key(|(_, s)| (s.priority, s.updated)),
+            ArbitrationPolicy::Latest => voting.max_by_key(|(_, s)| (s.updated, s.priority)),
+            ArbitrationPolicy::Unanimous => {
+                let voting: Vec<_> = voting.collect();
+                let (first, _) = *voting.first()?;
+                if !voting.iter().all(|(theme, _)| *theme == first) {
+                    return None;
+                }
+                voting.into_iter().max_by_key(|(_, s)| s.updated)
+            }
+        }
+    }
+
+    /// The theme of the highest-priority source that has one, for when
+    /// arbitration has no result, e.g. unanimous sources disagreeing
+    fn fallback(&self) -> Option<Theme> {
+        self.sources
+            .iter()
+            .filter(|s| s.theme.is_some())
+            .max_by_key(|s| s.priority)
+            .and_then(|s| s.theme)
+    }
+
+    /// Records a report and returns the arbitrated event if the theme changed
+    fn report(&mut self, index: usize, event: ThemeEvent) -> Option<ThemeEvent> {
+        self.sequence += 1;
+        let source = &mut self.sources[index];
+        source.theme = Some(event.theme);
+        source.source = Some(event.source);
+        source.updated = self.sequence;
+
+        let (theme, winner) = self.arbitrate()?;
+        if self.current == Some(theme) {
+            return None;
+        }
+
+        // Attribute the change to the winning source, or to this report if
+        // the winner hasn't reported since startup
+        let source = winner.source.unwrap_or(event.source);
+        self.current = Some(theme);
+        Some(ThemeEvent { theme, source, ..event })
+    }
+}
+
+/// Theme monitor combining several monitors under an arbitration policy.
+///
+/// `Priority` follows the highest-priority source that has an opinion,
+/// `Latest` follows whichever source reported most recently, and `Unanimous`
+/// only switches once all voting sources agree. The arbitrated theme is only
+/// reported when it changes. Without an arbitrated theme, the current theme is
+/// that of the highest-priority source.
+pub struct CompositeMonitor {
+    monitors: Vec<Box<dyn ThemeMonitor>>,
+    arbiter: Arc<Mutex<Arbiter>>,
+    events: EventSender,
+    running: AtomicBool,
+}
+
+impl CompositeMonitor {
+    pub fn new(policy: ArbitrationPolicy, events: EventSender) -> Self {
+        Self {
+            monitors: Vec::new(),
+            arbiter: Arc::new(Mutex::new(Arbiter {
+                policy,
+                sources: Vec::new(),
+                current: None,
+                sequence: 0,
+            })),
+            events,
+            running: AtomicBool::new(false),
+        }
+    }
+
+    /// Adds a source. `create` receives the sender the source's monitor must
+    /// report to. With `only` set, the source only votes for that theme.
+    pub fn add_source<C>(&mut self, priority: i32, only: Option<Theme>, create: C) -> Result<(), Box<dyn Error>>
+    where
+        C: FnOnce(EventSender) -> Result<Box<dyn ThemeMonitor>, Box<dyn Error>>,
+    {
+        let index = self.monitors.len();
+        let arbiter = Arc::clone(&self.arbiter);
+        let events = self.events.clone();
+
+        let monitor = create(EventSender::new(move |event| {
+            // Report outside the lock so handlers can't block other sources
+            let changed = arbiter.lock().unwrap().report(index, event);
+            if let Some(event) = changed {
+                events.send(event);
+            }
+        }))?;
+
+        self.arbiter.lock().unwrap().sources.push(SourceState {
+            priority,
+            only,
+            theme: None,
+            source: None,
+            updated: 0,
+        });
+        self.monitors.push(monitor);
+        Ok(())
+    }
+
+    fn source_themes(&self) -> Vec<Theme> {
+        self.monitors.iter().map(|monitor| monitor.get_current_theme()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.monitors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.monitors.is_empty()
+    }
+}
+
+impl ThemeMonitor for CompositeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        if self.running.load(Ordering::SeqCst) {
+            return Err("Monitor is already running".into());
+        }
+
+        {
+            let themes = self.source_themes();
+            let mut arbiter = self.arbiter.lock().unwrap();
+            for (source, theme) in arbiter.sources.iter_mut().zip(themes) {
+                source.theme = Some(theme);
+                source.updated = 0;
+            }
+            arbiter.current = arbiter.arbitrate().map(|(theme, _)| theme);
+        }
+
+        for (index, monitor) in self.monitors.iter().enumerate() {
+            if let Err(e) = monitor.start() {
+                for started in &self.monitors[..index] {
+                    let _ = started.stop();
+                }
+                return Err(e);
+            }
+        }
+
+        self.running.store(true, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        for monitor in &self.monitors {
+            monitor.stop()?;
+        }
+        Ok(())
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        let mut snapshot = self.arbiter.lock().unwrap().clone();
+        if let (true, Some(theme)) = (self.running.load(Ordering::SeqCst), snapshot.current) {
+            return theme;
+        }
+
+        // Not started yet or undecided: arbitrate over the sources' current themes
+        for (source, theme) in snapshot.sources.iter_mut().zip(self.source_themes()) {
+            source.theme = Some(theme);
+        }
+        snapshot
+            .arbitrate()
+            .map(|(theme, _)| theme)
+            .or_else(|| snapshot.fallback())
+            .unwrap_or(Theme::Light)
+    }
+}
+
+impl Drop for CompositeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An arbiter over sources with the given priorities and `only` filters,
+    /// none of which has reported yet
+    fn arbiter(policy: ArbitrationPolicy, sources: &[(i32, Option<Theme>)]) -> Arbiter {
+        Arbiter {
+            policy,
+            sources: sources
+                .iter()
+                .map(|&(priority, only)| SourceState {
+                    priority,
+                    only,
+                    theme: None,
+                    source: None,
+                    updated: 0,
+                })
+                .collect(),
+            current: None,
+            sequence: 0,
+        }
+    }
+
+    /// Sets the sources' themes as start() does, without reports
+    fn started(mut arbiter: Arbiter, themes: &[Theme]) -> Arbiter {
+        for (source, theme) in arbiter.sources.iter_mut().zip(themes) {
+            source.theme = Some(*theme);
+        }
+        arbiter.current = arbiter.arbitrate().map(|(theme, _)| theme);
+        arbiter
+    }
+
+    fn report(arbiter: &mut Arbiter, index: usize, theme: Theme) -> Option<Theme> {
+        arbiter
+            .report(index, ThemeEvent::new(theme, ThemeSource::System))
+            .map(|event| event.theme)
+    }
+
+    #[test]
+    fn priority_follows_the_highest_priority_source() {
+        let mut arbiter = started(
+            arbiter(ArbitrationPolicy::Priority, &[(0, None), (10, None)]),
+            &[Theme::Light, Theme::Light],
+        );
+        assert_eq!(report(&mut arbiter, 0, Theme::Dark), None);
+        assert_eq!(report(&mut arbiter, 1, Theme::Dark), Some(Theme::Dark));
+        assert_eq!(report(&mut arbiter, 0, Theme::Light), None);
+        assert_eq!(report(&mut arbiter, 1, Theme::Light), Some(Theme::Light));
+    }
+
+    #[test]
+    fn latest_breaks_ties_by_priority_before_any_report() {
+        let arbiter = started(
+            arbiter(ArbitrationPolicy::Latest, &[(5, None), (0, None)]),
+            &[Theme::Dark, Theme::Light],
+        );
+        assert_eq!(arbiter.current, Some(Theme::Dark));
+
+        let mut arbiter = arbiter;
+        assert_eq!(report(&mut arbiter, 1, Theme::Light), Some(Theme::Light));
+        assert_eq!(report(&mut arbiter, 0, Theme::Dark), Some(Theme::Dark));
+    }
+
+    #[test]
+    fn unanimous_waits_for_agreement() {
+        let mut arbiter = started(
+            arbiter(ArbitrationPolicy::Unanimous, &[(0, None), (0, None)]),
+            &[Theme::Light, Theme::Light],
+        );
+        assert_eq!(report(&mut arbiter, 0, Theme::Dark), None);
+        assert_eq!(arbiter.current, Some(Theme::Light));
+        assert_eq!(report(&mut arbiter, 1, Theme::Dark), Some(Theme::Dark));
+    }
+
+    #[test]
+    fn unanimous_disagreement_falls_back_to_the_highest_priority() {
+        let arbiter = started(
+            arbiter(ArbitrationPolicy::Unanimous, &[(0, None), (10, None)]),
+            &[Theme::Light, Theme::Dark],
+        );
+        assert_eq!(arbiter.current, None);
+        assert_eq!(arbiter.fallback(), Some(Theme::Dark));
+    }
+
+    #[test]
+    fn only_sources_abstain_for_the_other_theme() {
+        let mut arbiter = started(
+            arbiter(ArbitrationPolicy::Priority, &[(0, None), (10, Some(Theme::Dark))]),
+            &[Theme::Light, Theme::Light],
+        );
+        assert_eq!(arbiter.current, Some(Theme::Light));
+        assert_eq!(report(&mut arbiter, 1, Theme::Dark), Some(Theme::Dark));
+        assert_eq!(report(&mut arbiter, 0, Theme::Light), None);
+        assert_eq!(report(&mut arbiter, 1, Theme::Light), Some(Theme::Light));
+    }
+
+    #[test]
+    fn unchanged_result_emits_nothing() {
+        let mut arbiter = started(arbiter(ArbitrationPolicy::Priority, &[(0, None)]), &[Theme::Light]);
+        assert_eq!(report(&mut arbiter, 0, Theme::Light), None);
+        assert_eq!(report(&mut arbiter, 0, Theme::Dark), Some(Theme::Dark));
+        assert_eq!(report(&mut arbiter, 0, Theme::Dark), None);
+    }
+
+    /// A source that always reports `theme`
+    struct Fixed(Theme);
+
+    impl ThemeMonitor for Fixed {
+        fn start(&self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn stop(&self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        fn get_current_theme(&self) -> Theme {
+            self.0
+        }
+    }
+
+    #[test]
+    fn current_theme_of_disagreeing_unanimous_sources_is_the_highest_priority() {
+        let mut monitor = CompositeMonitor::new(ArbitrationPolicy::Unanimous, EventSender::new(|_| {}));
+        monitor.add_source(0, None, |_| Ok(Box::new(Fixed(Theme::Light)))).unwrap();
+        monitor.add_source(10, None, |_| Ok(Box::new(Fixed(Theme::Dark)))).unwrap();
+        assert_eq!(monitor.get_current_theme(), Theme::Dark);
+
+        monitor.start().unwrap();
+        assert_eq!(monitor.get_current_theme(), Theme::Dark);
+    }
+}
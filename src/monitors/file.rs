@@ -0,0 +1,164 @@
+use crate::logging::{log_error, log_warn};
+use crate::{Theme, ThemeMonitor};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Default location of the state file, `~/.local/state/theme`
+pub fn default_theme_file() -> PathBuf {
+    dirs::home_dir()
+        .map(|home| home.join(".local/state/theme"))
+        .unwrap_or_else(|| PathBuf::from("theme"))
+}
+
+fn read_theme_from(path: &Path) -> Result<(String, Theme), Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)?;
+    let theme = content.parse::<Theme>()?;
+    Ok((content, theme))
+}
+
+/// Theme monitor driven by a plain file containing `light` or `dark`
+pub struct FileThemeMonitor {
+    path: PathBuf,
+    callback: Arc<dyn Fn(Theme) + Send + Sync>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl FileThemeMonitor {
+    pub fn new<F>(path: PathBuf, callback: F) -> Self
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Self {
+            path,
+            callback: Arc::new(callback),
+            watcher: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the theme currently stored in the file
+    pub fn read_theme(&self) -> Result<Theme, Box<dyn Error>> {
+        read_theme_from(&self.path).map(|(_, theme)| theme)
+    }
+}
+
+impl ThemeMonitor for FileThemeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        let mut watcher_slot = self.watcher.lock().unwrap();
+        if watcher_slot.is_some() {
+            return Err("Monitor is already running".into());
+        }
+
+        let directory = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let file_name: OsString = self
+            .path
+            .file_name()
+            .ok_or_else(|| format!("Theme file path has no file name: {:?}", self.path))?
+            .to_owned();
+
+        // The file itself may not exist yet, but its directory has to
+        std::fs::create_dir_all(&directory)?;
+
+        let path = self.path.clone();
+        let callback = Arc::clone(&self.callback);
+        let last_content = Mutex::new(read_theme_from(&path).ok().map(|(content, _)| content));
+
+        // Editors and `mv` replace the file, so watch the directory and
+        // filter by name instead of watching the file's inode
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<Event>| {
+            let event = match result {
+                Ok(event) => event,
+                Err(e) => {
+                    log_error(&format!("Error watching theme file: {}", e));
+                    return;
+                }
+            };
+
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+            if !event.paths.iter().any(|p| p.file_name() == Some(file_name.as_os_str())) {
+                return;
+            }
+
+            // A missing or truncated file means a writer hasn't finished yet;
+            // the event for its final write will follow
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) if !content.trim().is_empty() => content,
+                _ => return,
+            };
+
+            match content.parse::<Theme>() {
+                Ok(theme) => {
+                    let mut last_content = last_content.lock().unwrap();
+                    if last_content.as_deref() != Some(content.as_str()) {
+                        *last_content = Some(content);
+                        callback(theme);
+                    }
+                }
+                Err(e) => log_warn(&format!("Ignoring theme file {:?}: {}", path, e)),
+            }
+        })?;
+        watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+
+        *watcher_slot = Some(watcher);
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        // Dropping the watcher shuts down its event thread
+        self.watcher.lock().unwrap().take();
+        Ok(())
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        self.read_theme().unwrap_or(Theme::Light)
+    }
+}
+
+impl Drop for FileThemeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn reports_each_change_of_the_file_once() {
+        let dir = std::env::temp_dir().join(format!("theme-switcher-file-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("theme");
+        let (themes_sender, themes) = mpsc::channel();
+        let monitor = FileThemeMonitor::new(path.clone(), move |theme| {
+            let _ = themes_sender.send(theme);
+        });
+        monitor.start().unwrap();
+
+        for content in ["dark\n", "dark\n", "light\n"] {
+            std::fs::write(&path, content).unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+        }
+
+        // Leaves the watcher time to deliver the last write
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!(themes.try_iter().collect::<Vec<_>>(), [Theme::Dark, Theme::Light]);
+        assert_eq!(monitor.get_current_theme(), Theme::Light);
+
+        monitor.stop().unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,18 @@
+//! Platform-independent theme monitors
+
+pub mod ambient;
+pub mod clock;
+pub mod command;
+pub mod composite;
+pub mod file;
+pub mod schedule;
+pub mod solar;
+mod stop;
+
+pub use ambient::AmbientThemeMonitor;
+pub use clock::{Clock, SystemClock};
+pub use command::CommandThemeMonitor;
+pub use composite::CompositeMonitor;
+pub use file::FileThemeMonitor;
+pub use schedule::{DailySchedule, Schedule, ScheduleThemeMonitor};
+pub use solar::SolarSchedule;
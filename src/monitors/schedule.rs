@@ -0,0 +1,319 @@
+use super::clock::{Clock, SystemClock};
+use super::stop::StopSignal;
+use crate::config_file::ScheduleConfig;
+use crate::{Theme, ThemeMonitor};
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Upper bound on a single sleep. Monotonic timers don't advance while the
+/// machine is suspended, so the schedule is re-evaluated at least this often.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+/// Switch times for a single day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DayTimes {
+    pub light: NaiveTime,
+    pub dark: NaiveTime,
+}
+
+/// Source of theme transitions for a given calendar day
+pub trait DailySchedule: Send + Sync {
+    /// The transitions that take effect on `date`, in local time.
+    ///
+    /// Every day must report at least one transition; a day without a change
+    /// (e.g. polar night) reports its theme at midnight.
+    fn transitions(&self, date: NaiveDate) -> Vec<(NaiveDateTime, Theme)>;
+
+    /// Theme in effect at the given local time
+    fn theme_at(&self, now: NaiveDateTime) -> Theme {
+        let today = now.date();
+        transitions_between(self, today - Days::new(2), today + Days::new(1))
+            .into_iter()
+            .rev()
+            .find(|(at, _)| *at <= now)
+            .map(|(_, theme)| theme)
+            .unwrap_or(Theme::Light)
+    }
+
+    /// First transition strictly after the given local time
+    fn next_transition(&self, now: NaiveDateTime) -> (NaiveDateTime, Theme) {
+        let today = now.date();
+        transitions_between(self, today - Days::new(1), today + Days::new(2))
+            .into_iter()
+            .find(|(at, _)| *at > now)
+            .expect("every day has at least one transition")
+    }
+}
+
+/// All transitions of the days in `first..=last`, sorted chronologically.
+/// Adjacent days are included because a day's events can fall on the
+/// neighbouring local date.
+fn transitions_between<S: DailySchedule + ?Sized>(
+    schedule: &S,
+    first: NaiveDate,
+    last: NaiveDate,
+) -> Vec<(NaiveDateTime, Theme)> {
+    let mut transitions: Vec<_> = first
+        .iter_days()
+        .take_while(|date| *date <= last)
+        .flat_map(|date| schedule.transitions(date))
+        .collect();
+    transitions.sort_by_key(|(at, _)| *at);
+    transitions
+}
+
+/// Weekly schedule of fixed light/dark switch times
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+    default: DayTimes,
+    overrides: HashMap<Weekday, DayTimes>,
+}
+
+impl Schedule {
+    pub fn new(light: NaiveTime, dark: NaiveTime) -> Self {
+        Self {
+            default: DayTimes { light, dark },
+            overrides: HashMap::new(),
+        }
+    }
+
+    pub fn with_override(mut self, day: Weekday, times: DayTimes) -> Self {
+        self.overrides.insert(day, times);
+        self
+    }
+
+    pub fn from_config(config: &ScheduleConfig) -> Self {
+        let mut schedule = Self::new(config.light, config.dark);
+        for (day, times) in &config.days {
+            schedule = schedule.with_override(
+                *day,
+                DayTimes {
+                    light: times.light.unwrap_or(config.light),
+                    dark: times.dark.unwrap_or(config.dark),
+                },
+            );
+        }
+        schedule
+    }
+
+    pub fn times_for(&self, date: NaiveDate) -> DayTimes {
+        self.overrides
+            .get(&date.weekday())
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+impl DailySchedule for Schedule {
+    fn transitions(&self, date: NaiveDate) -> Vec<(NaiveDateTime, Theme)> {
+        let times = self.times_for(date);
+        vec![
+            (date.and_time(times.light), Theme::Light),
+            (date.and_time(times.dark), Theme::Dark),
+        ]
+    }
+}
+
+/// Theme monitor that switches at the transitions of a [`DailySchedule`]
+pub struct ScheduleThemeMonitor {
+    schedule: Arc<dyn DailySchedule>,
+    clock: Arc<dyn Clock>,
+    callback: Arc<dyn Fn(Theme) + Send + Sync>,
+    worker: Mutex<Option<(StopSignal, JoinHandle<()>)>>,
+}
+
+impl ScheduleThemeMonitor {
+    pub fn new<S, F>(schedule: S, callback: F) -> Self
+    where
+        S: DailySchedule + 'static,
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Self::with_clock(schedule, Arc::new(SystemClock), callback)
+    }
+
+    /// Creates a monitor driven by a custom clock, e.g. a fixed one in tests
+    pub fn with_clock<S, F>(schedule: S, clock: Arc<dyn Clock>, callback: F) -> Self
+    where
+        S: DailySchedule + 'static,
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Self {
+            schedule: Arc::new(schedule),
+            clock,
+            callback: Arc::new(callback),
+            worker: Mutex::new(None),
+        }
+    }
+}
+
+impl ThemeMonitor for ScheduleThemeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        let mut worker = self.worker.lock().unwrap();
+        if worker.is_some() {
+            return Err("Monitor is already running".into());
+        }
+
+        let schedule = Arc::clone(&self.schedule);
+        let clock = Arc::clone(&self.clock);
+        let callback = Arc::clone(&self.callback);
+        let stop = StopSignal::new();
+        let thread_stop = stop.clone();
+
+        let thread = std::thread::Builder::new()
+            .name("schedule-monitor".to_string())
+            .spawn(move || {
+                let mut current = schedule.theme_at(clock.now());
+                loop {
+                    let (next, _) = schedule.next_transition(clock.now());
+                    if thread_stop.wait(clock.until(next).min(MAX_SLEEP)) {
+                        break;
+                    }
+
+                    let theme = schedule.theme_at(clock.now());
+                    if theme != current {
+                        current = theme;
+                        callback(theme);
+                    }
+                }
+            })?;
+
+        *worker = Some((stop, thread));
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        if let Some((stop, thread)) = self.worker.lock().unwrap().take() {
+            stop.stop();
+            let _ = thread.join();
+        }
+        Ok(())
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        self.schedule.theme_at(self.clock.now())
+    }
+}
+
+impl Drop for ScheduleThemeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    /// A clock standing at a fixed time
+    struct FixedClock(NaiveDateTime);
+
+    impl Clock for FixedClock {
+        fn now(&self) -> NaiveDateTime {
+            self.0
+        }
+
+        fn until(&self, target: NaiveDateTime) -> Duration {
+            (target - self.0).to_std().unwrap_or(Duration::ZERO)
+        }
+    }
+
+    /// A clock that jumps straight to whatever time it is asked to wait for
+    struct SteppingClock(Mutex<NaiveDateTime>);
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> NaiveDateTime {
+            *self.0.lock().unwrap()
+        }
+
+        fn until(&self, target: NaiveDateTime) -> Duration {
+            *self.0.lock().unwrap() = target;
+            Duration::ZERO
+        }
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    /// A time on a day in the week of Monday 2024-06-03
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 6, day).unwrap().and_time(time(hour, minute))
+    }
+
+    fn schedule() -> Schedule {
+        Schedule::new(time(7, 0), time(19, 0))
+    }
+
+    #[test]
+    fn theme_switches_at_transition_times() {
+        let schedule = schedule();
+        assert_eq!(schedule.theme_at(at(3, 6, 59)), Theme::Dark);
+        assert_eq!(schedule.theme_at(at(3, 7, 0)), Theme::Light);
+        assert_eq!(schedule.theme_at(at(3, 18, 59)), Theme::Light);
+        assert_eq!(schedule.theme_at(at(3, 19, 0)), Theme::Dark);
+    }
+
+    #[test]
+    fn next_transition_is_strictly_later() {
+        let schedule = schedule();
+        assert_eq!(schedule.next_transition(at(3, 6, 0)), (at(3, 7, 0), Theme::Light));
+        assert_eq!(schedule.next_transition(at(3, 7, 0)), (at(3, 19, 0), Theme::Dark));
+    }
+
+    #[test]
+    fn next_transition_wraps_past_midnight() {
+        let schedule = schedule();
+        assert_eq!(schedule.next_transition(at(3, 19, 0)), (at(4, 7, 0), Theme::Light));
+        assert_eq!(schedule.next_transition(at(3, 23, 59)), (at(4, 7, 0), Theme::Light));
+    }
+
+    #[test]
+    fn dark_time_after_midnight_belongs_to_its_own_day() {
+        let schedule = Schedule::new(time(10, 0), time(1, 0));
+        assert_eq!(schedule.theme_at(at(4, 0, 30)), Theme::Light);
+        assert_eq!(schedule.theme_at(at(4, 1, 0)), Theme::Dark);
+        assert_eq!(schedule.next_transition(at(3, 23, 0)), (at(4, 1, 0), Theme::Dark));
+    }
+
+    #[test]
+    fn weekday_overrides_apply_to_their_day() {
+        let weekend = DayTimes {
+            light: time(9, 0),
+            dark: time(22, 0),
+        };
+        let schedule = schedule().with_override(Weekday::Sat, weekend);
+
+        // Friday evening switches at the default time, Saturday at its own
+        assert_eq!(schedule.next_transition(at(7, 12, 0)), (at(7, 19, 0), Theme::Dark));
+        assert_eq!(schedule.next_transition(at(7, 19, 0)), (at(8, 9, 0), Theme::Light));
+        assert_eq!(schedule.theme_at(at(8, 8, 0)), Theme::Dark);
+        assert_eq!(schedule.theme_at(at(8, 21, 0)), Theme::Light);
+    }
+
+    #[test]
+    fn monitor_reads_theme_from_clock() {
+        let monitor = ScheduleThemeMonitor::with_clock(schedule(), Arc::new(FixedClock(at(3, 12, 0))), |_| {});
+        assert_eq!(monitor.get_current_theme(), Theme::Light);
+    }
+
+    #[test]
+    fn monitor_reports_transitions() {
+        let clock = Arc::new(SteppingClock(Mutex::new(at(3, 18, 0))));
+        let (sender, themes) = mpsc::channel();
+        let monitor = ScheduleThemeMonitor::with_clock(schedule(), clock, move |theme| {
+            let _ = sender.send(theme);
+        });
+
+        monitor.start().unwrap();
+        let first = themes.recv_timeout(Duration::from_secs(5)).unwrap();
+        let second = themes.recv_timeout(Duration::from_secs(5)).unwrap();
+        monitor.stop().unwrap();
+
+        assert_eq!((first, second), (Theme::Dark, Theme::Light));
+    }
+}
@@ -0,0 +1,305 @@
+use super::schedule::DailySchedule;
+use crate::Theme;
+use crate::config_file::{SolarConfig, Twilight};
+use chrono::{Datelike, Local, NaiveDate, NaiveDateTime, TimeDelta, TimeZone};
+
+impl Twilight {
+    /// Zenith angle of the sun, in degrees, that marks the event
+    pub fn zenith(self) -> f64 {
+        match self {
+            // Accounts for refraction and the sun's apparent radius
+            Twilight::Official => 90.833,
+            Twilight::Civil => 96.0,
+            Twilight::Nautical => 102.0,
+        }
+    }
+}
+
+/// Sun events for a single day, in UTC
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SunEvents {
+    Normal {
+        rise: NaiveDateTime,
+        set: NaiveDateTime,
+    },
+    /// The sun never drops below the zenith angle (midnight sun)
+    AlwaysUp,
+    /// The sun never rises above the zenith angle (polar night)
+    AlwaysDown,
+}
+
+/// Computes sunrise and sunset for a day at the given location.
+///
+/// Uses the sunrise/sunset algorithm from the US Naval Observatory's
+/// *Almanac for Computers*, which is accurate to within a couple of minutes
+/// for latitudes below the polar circles and needs no network access.
+pub fn sun_events(date: NaiveDate, latitude: f64, longitude: f64, zenith: f64) -> SunEvents {
+    let day_of_year = date.ordinal() as f64;
+    let longitude_hours = longitude / 15.0;
+
+    let event_utc_hours = |rising: bool| -> Result<f64, SunEvents> {
+        // Approximate time of the event, in days since the start of the year
+        let base = if rising { 6.0 } else { 18.0 };
+        let t = day_of_year + (base - longitude_hours) / 24.0;
+
+        // Sun's mean anomaly and true longitude
+        let mean_anomaly = 0.9856 * t - 3.289;
+        let true_longitude = (mean_anomaly
+            + 1.916 * sin_deg(mean_anomaly)
+            + 0.020 * sin_deg(2.0 * mean_anomaly)
+            + 282.634)
+            .rem_euclid(360.0);
+
+        // Right ascension, moved into the same quadrant as the true longitude
+        let right_ascension = atan_deg(0.91764 * tan_deg(true_longitude)).rem_euclid(360.0);
+        let right_ascension = right_ascension
+            + ((true_longitude / 90.0).floor() * 90.0 - (right_ascension / 90.0).floor() * 90.0);
+        let right_ascension_hours = right_ascension / 15.0;
+
+        // Declination and local hour angle
+        let sin_declination = 0.39782 * sin_deg(true_longitude);
+        let cos_declination = sin_declination.asin().cos();
+        let cos_hour_angle = (cos_deg(zenith) - sin_declination * sin_deg(latitude))
+            / (cos_declination * cos_deg(latitude));
+
+        if cos_hour_angle > 1.0 {
+            return Err(SunEvents::AlwaysDown);
+        }
+        if cos_hour_angle < -1.0 {
+            return Err(SunEvents::AlwaysUp);
+        }
+
+        let hour_angle = if rising {
+            360.0 - acos_deg(cos_hour_angle)
+        } else {
+            acos_deg(cos_hour_angle)
+        } / 15.0;
+
+        // Local mean time of the event, converted to UTC. The result is not
+        // wrapped into 0..24 so events on the neighbouring UTC day keep their date.
+        let local_mean_time =
+            (hour_angle + right_ascension_hours - 0.06571 * t - 6.622).rem_euclid(24.0);
+        Ok(local_mean_time - longitude_hours)
+    };
+
+    let to_datetime = |hours: f64| {
+        date.and_hms_opt(0, 0, 0).unwrap() + TimeDelta::milliseconds((hours * 3_600_000.0).round() as i64)
+    };
+
+    match (event_utc_hours(true), event_utc_hours(false)) {
+        (Ok(rise), Ok(set)) => SunEvents::Normal {
+            rise: to_datetime(rise),
+            set: to_datetime(set),
+        },
+        (Err(polar), _) | (_, Err(polar)) => polar,
+    }
+}
+
+fn sin_deg(degrees: f64) -> f64 {
+    degrees.to_radians().sin()
+}
+
+fn cos_deg(degrees: f64) -> f64 {
+    degrees.to_radians().cos()
+}
+
+fn tan_deg(degrees: f64) -> f64 {
+    degrees.to_radians().tan()
+}
+
+fn atan_deg(value: f64) -> f64 {
+    value.atan().to_degrees()
+}
+
+fn acos_deg(value: f64) -> f64 {
+    value.acos().to_degrees()
+}
+
+/// Local switch times for a single day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SolarDay {
+    Normal {
+        sunrise: NaiveDateTime,
+        sunset: NaiveDateTime,
+        light: NaiveDateTime,
+        dark: NaiveDateTime,
+    },
+    AlwaysUp,
+    AlwaysDown,
+}
+
+/// Schedule that switches to light at sunrise and to dark at sunset
+#[derive(Debug, Clone)]
+pub struct SolarSchedule<Tz: TimeZone = Local> {
+    latitude: f64,
+    longitude: f64,
+    twilight: Twilight,
+    sunrise_offset: TimeDelta,
+    sunset_offset: TimeDelta,
+    timezone: Tz,
+}
+
+impl SolarSchedule<Local> {
+    pub fn new(latitude: f64, longitude: f64) -> Result<Self, String> {
+        Self::with_timezone(latitude, longitude, Local)
+    }
+
+    pub fn from_config(config: &SolarConfig) -> Result<Self, String> {
+        let (Some(latitude), Some(longitude)) = (config.latitude, config.longitude) else {
+            return Err("[solar] latitude and longitude must be set".to_string());
+        };
+
+        Ok(Self::new(latitude, longitude)?
+            .with_twilight(config.twilight)
+            .with_offsets(config.sunrise_offset, config.sunset_offset))
+    }
+}
+
+impl<Tz: TimeZone> SolarSchedule<Tz> {
+    /// Creates a schedule reporting local times in `timezone`
+    pub fn with_timezone(latitude: f64, longitude: f64, timezone: Tz) -> Result<Self, String> {
+        if !(-90.0..=90.0).contains(&latitude) {
+            return Err(format!("latitude {} is out of range (-90 to 90)", latitude));
+        }
+        if !(-180.0..=180.0).contains(&longitude) {
+            return Err(format!("longitude {} is out of range (-180 to 180)", longitude));
+        }
+
+        Ok(Self {
+            latitude,
+            longitude,
+            twilight: Twilight::default(),
+            sunrise_offset: TimeDelta::zero(),
+            sunset_offset: TimeDelta::zero(),
+            timezone,
+        })
+    }
+
+    pub fn with_twilight(mut self, twilight: Twilight) -> Self {
+        self.twilight = twilight;
+        self
+    }
+
+    /// Shifts the switch times; positive offsets switch after the event
+    pub fn with_offsets(mut self, sunrise_offset: TimeDelta, sunset_offset: TimeDelta) -> Self {
+        self.sunrise_offset = sunrise_offset;
+        self.sunset_offset = sunset_offset;
+        self
+    }
+
+    /// Computes the sun events and switch times for a local date
+    pub fn day(&self, date: NaiveDate) -> SolarDay {
+        let to_local = |utc: NaiveDateTime| self.timezone.from_utc_datetime(&utc).naive_local();
+
+        match sun_events(date, self.latitude, self.longitude, self.twilight.zenith()) {
+            SunEvents::Normal { rise, set } => {
+                let sunrise = to_local(rise);
+                let sunset = to_local(set);
+                SolarDay::Normal {
+                    sunrise,
+                    sunset,
+                    light: sunrise + self.sunrise_offset,
+                    dark: sunset + self.sunset_offset,
+                }
+            }
+            SunEvents::AlwaysUp => SolarDay::AlwaysUp,
+            SunEvents::AlwaysDown => SolarDay::AlwaysDown,
+        }
+    }
+}
+
+impl<Tz: TimeZone + Send + Sync> DailySchedule for SolarSchedule<Tz> {
+    fn transitions(&self, date: NaiveDate) -> Vec<(NaiveDateTime, Theme)> {
+        let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+        match self.day(date) {
+            SolarDay::Normal { light, dark, .. } => vec![(light, Theme::Light), (dark, Theme::Dark)],
+            SolarDay::AlwaysUp => vec![(midnight, Theme::Light)],
+            SolarDay::AlwaysDown => vec![(midnight, Theme::Dark)],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::FixedOffset;
+
+    const BERLIN: (f64, f64) = (52.52, 13.405);
+    const SYDNEY: (f64, f64) = (-33.8688, 151.2093);
+    const TROMSO: (f64, f64) = (69.6492, 18.9553);
+
+    fn schedule(location: (f64, f64), utc_offset_hours: i32) -> SolarSchedule<FixedOffset> {
+        let timezone = FixedOffset::east_opt(utc_offset_hours * 3600).unwrap();
+        SolarSchedule::with_timezone(location.0, location.1, timezone).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn time(date: NaiveDate, hour: u32, minute: u32) -> NaiveDateTime {
+        date.and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    #[track_caller]
+    fn assert_near(actual: NaiveDateTime, expected: NaiveDateTime) {
+        let error = (actual - expected).num_seconds().abs();
+        assert!(error <= 5 * 60, "expected about {}, got {}", expected, actual);
+    }
+
+    #[track_caller]
+    fn assert_sun(day: SolarDay, sunrise: NaiveDateTime, sunset: NaiveDateTime) {
+        let SolarDay::Normal { sunrise: rise, sunset: set, .. } = day else {
+            panic!("expected a sunrise and a sunset, got {:?}", day);
+        };
+        assert_near(rise, sunrise);
+        assert_near(set, sunset);
+    }
+
+    #[test]
+    fn mid_latitude_summer_solstice() {
+        let day = date(2024, 6, 21);
+        assert_sun(schedule(BERLIN, 2).day(day), time(day, 4, 43), time(day, 21, 33));
+    }
+
+    #[test]
+    fn southern_hemisphere_summer_crosses_the_utc_date() {
+        // Sunrise in Sydney is still the previous day in UTC
+        let day = date(2024, 12, 21);
+        assert_sun(schedule(SYDNEY, 11).day(day), time(day, 5, 41), time(day, 20, 5));
+    }
+
+    #[test]
+    fn dst_transition_shifts_local_times_by_an_hour() {
+        let before = date(2024, 3, 30);
+        let after = date(2024, 3, 31);
+        assert_sun(schedule(BERLIN, 1).day(before), time(before, 5, 46), time(before, 18, 36));
+        assert_sun(schedule(BERLIN, 2).day(after), time(after, 6, 44), time(after, 19, 38));
+    }
+
+    #[test]
+    fn offsets_move_the_switch_times() {
+        let day = date(2024, 6, 21);
+        let schedule = schedule(BERLIN, 2)
+            .with_offsets(TimeDelta::minutes(30), TimeDelta::minutes(-15));
+        let SolarDay::Normal { sunrise, sunset, light, dark } = schedule.day(day) else {
+            panic!("expected a sunrise and a sunset");
+        };
+        assert_eq!(light, sunrise + TimeDelta::minutes(30));
+        assert_eq!(dark, sunset - TimeDelta::minutes(15));
+        assert_eq!(schedule.transitions(day), vec![(light, Theme::Light), (dark, Theme::Dark)]);
+    }
+
+    #[test]
+    fn polar_day_and_night_schedule_no_sun_events() {
+        let schedule = schedule(TROMSO, 2);
+        let june = date(2024, 6, 21);
+        let december = date(2024, 12, 21);
+
+        assert_eq!(schedule.day(june), SolarDay::AlwaysUp);
+        assert_eq!(schedule.day(december), SolarDay::AlwaysDown);
+        // Only the theme for the whole day, set at midnight
+        assert_eq!(schedule.transitions(june), vec![(time(june, 0, 0), Theme::Light)]);
+        assert_eq!(schedule.transitions(december), vec![(time(december, 0, 0), Theme::Dark)]);
+    }
+}
@@ -0,0 +1,28 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/// Interruptible sleep shared between a monitor and its worker thread
+#[derive(Clone, Default)]
+pub(crate) struct StopSignal(Arc<(Mutex<bool>, Condvar)>);
+
+impl StopSignal {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn stop(&self) {
+        let (stopped, condvar) = &*self.0;
+        *stopped.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    /// Sleeps for up to `timeout`, returning true once a stop was requested
+    pub(crate) fn wait(&self, timeout: Duration) -> bool {
+        let (stopped, condvar) = &*self.0;
+        let guard = stopped.lock().unwrap();
+        let (guard, _) = condvar
+            .wait_timeout_while(guard, timeout, |stopped| !*stopped)
+            .unwrap();
+        *guard
+    }
+}
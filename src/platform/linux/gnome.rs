@@ -0,0 +1,342 @@
+use super::SignalWorker;
+use super::portal::SettingsProxy;
+use crate::{Theme, ThemeMonitor};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use zbus::blocking::Connection;
+use zbus::proxy;
+use zbus::zvariant::Value;
+
+const INTERFACE_SCHEMA: &str = "org.gnome.desktop.interface";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+const GTK_THEME_KEY: &str = "gtk-theme";
+
+/// Program reading the keys in sessions without a settings portal
+const GSETTINGS: &str = "gsettings";
+
+/// dconf paths backing the keys we read, used to filter change notifications
+const WATCHED_PATHS: [&str; 2] = [
+    "/org/gnome/desktop/interface/color-scheme",
+    "/org/gnome/desktop/interface/gtk-theme",
+];
+
+#[proxy(
+    interface = "ca.desrt.dconf.Writer",
+    default_service = "ca.desrt.dconf",
+    default_path = "/ca/desrt/dconf/Writer/user"
+)]
+trait Writer {
+    #[zbus(signal)]
+    fn notify(&self, prefix: &str, changes: Vec<&str>, tag: &str) -> zbus::Result<()>;
+}
+
+/// Resolves the GNOME interface settings to a theme.
+///
+/// An explicit `prefer-dark`/`prefer-light` color scheme wins. Otherwise
+/// (`default`, or GNOME releases without the key) a GTK theme name ending in
+/// `-dark` selects the dark theme.
+fn resolve_theme(color_scheme: Option<&str>, gtk_theme: Option<&str>) -> Option<Theme> {
+    match color_scheme {
+        Some("prefer-dark") => return Some(Theme::Dark),
+        Some("prefer-light") => return Some(Theme::Light),
+        _ => {}
+    }
+
+    match gtk_theme {
+        Some(name) if name.to_lowercase().ends_with("-dark") => Some(Theme::Dark),
+        Some(_) => Some(Theme::Light),
+        None if color_scheme.is_some() => Some(Theme::Light),
+        None => None,
+    }
+}
+
+/// Returns true if a dconf `Notify` signal covers one of the watched keys.
+///
+/// dconf reports either the full key path with an empty change, or a
+/// directory prefix with the changed entries relative to it.
+fn touches_watched_keys(prefix: &str, changes: &[&str]) -> bool {
+    changes.iter().any(|change| {
+        let path = format!("{}{}", prefix, change);
+        WATCHED_PATHS.iter().any(|watched| watched.starts_with(&path))
+    })
+}
+
+/// Unwraps the string in a `Settings.Read` reply, which nests it in a variant
+fn value_to_string(value: &Value<'_>) -> Option<String> {
+    match value {
+        Value::Value(inner) => value_to_string(inner),
+        Value::Str(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Reads a string key of the interface schema over the bus. dconf's own bus
+/// interface can only write, so this asks the settings portal, whose GNOME
+/// and GTK backends serve the `org.gnome.desktop.interface` namespace. In
+/// sessions without a portal the key is read with `gsettings` instead, on a
+/// blocking thread so the executor shared with the signal worker keeps going.
+async fn read_key(connection: &zbus::Connection, gsettings: &Path, key: &str) -> Option<String> {
+    let value = async {
+        let proxy = SettingsProxy::new(connection).await?;
+        proxy.read(INTERFACE_SCHEMA, key).await
+    };
+    match value.await {
+        Ok(value) => value_to_string(&value),
+        Err(_) => {
+            let (gsettings, key) = (gsettings.to_path_buf(), key.to_string());
+            blocking::unblock(move || gsettings_get(&gsettings, &key)).await
+        }
+    }
+}
+
+/// Reads the color scheme and GTK theme and resolves them to a theme
+async fn read_theme(connection: &zbus::Connection, gsettings: &Path) -> Option<Theme> {
+    let color_scheme = read_key(connection, gsettings, COLOR_SCHEME_KEY).await;
+    let gtk_theme = read_key(connection, gsettings, GTK_THEME_KEY).await;
+    resolve_theme(color_scheme.as_deref(), gtk_theme.as_deref())
+}
+
+/// Reads a string key with the `gsettings` program, stripping the GVariant
+/// quoting
+fn gsettings_get(gsettings: &Path, key: &str) -> Option<String> {
+    let output = Command::new(gsettings)
+        .args(["get", INTERFACE_SCHEMA, key])
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    let value = String::from_utf8_lossy(&output.stdout);
+    Some(value.trim().trim_matches('\'').to_string())
+}
+
+/// Theme monitor that follows `org.gnome.desktop.interface` through dconf change notifications
+pub struct GnomeThemeMonitor {
+    connection: Connection,
+    callback: Arc<dyn Fn(Theme) + Send + Sync>,
+    signals: SignalWorker,
+    gsettings: PathBuf,
+}
+
+impl GnomeThemeMonitor {
+    /// Creates a monitor connected to the user's session bus
+    pub fn new<F>(callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Ok(Self::with_connection(Connection::session()?, callback))
+    }
+
+    /// Creates a monitor on an existing connection, e.g. a private bus running a stub dconf writer
+    pub fn with_connection<F>(connection: Connection, callback: F) -> Self
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Self {
+            connection,
+            callback: Arc::new(callback),
+            signals: SignalWorker::default(),
+            gsettings: PathBuf::from(GSETTINGS),
+        }
+    }
+
+    /// Reads the current theme from GSettings
+    pub fn read_theme(&self) -> Result<Theme, Box<dyn Error>> {
+        zbus::block_on(read_theme(self.connection.inner(), &self.gsettings))
+            .ok_or_else(|| format!("Unable to read {} settings", INTERFACE_SCHEMA).into())
+    }
+}
+
+impl ThemeMonitor for GnomeThemeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        let connection = self.connection.inner().clone();
+        let changes = zbus::block_on(async {
+            let proxy = WriterProxy::new(&connection).await?;
+            proxy.receive_notify().await
+        })?;
+
+        let callback = Arc::clone(&self.callback);
+        let gsettings = self.gsettings.clone();
+        self.signals.start("gnome-monitor", changes, move |signal| {
+            let relevant = signal
+                .args()
+                .map(|args| touches_watched_keys(args.prefix(), args.changes()))
+                .unwrap_or(false);
+            let connection = connection.clone();
+            let callback = Arc::clone(&callback);
+            let gsettings = gsettings.clone();
+            async move {
+                if !relevant {
+                    return;
+                }
+                if let Some(theme) = read_theme(&connection, &gsettings).await {
+                    callback(theme);
+                }
+            }
+        })
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        self.signals.stop();
+        Ok(())
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        self.read_theme().unwrap_or(Theme::Light)
+    }
+}
+
+impl Drop for GnomeThemeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Mutex, mpsc};
+    use std::time::Duration;
+    use zbus::blocking::connection::Builder;
+    use zbus::zvariant::OwnedValue;
+
+    /// Serves the interface schema like a settings portal backend
+    struct StubPortal {
+        color_scheme: Arc<Mutex<String>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.portal.Settings")]
+    impl StubPortal {
+        fn read(&self, namespace: &str, key: &str) -> zbus::fdo::Result<OwnedValue> {
+            let value = match (namespace, key) {
+                (INTERFACE_SCHEMA, COLOR_SCHEME_KEY) => self.color_scheme.lock().unwrap().clone(),
+                (INTERFACE_SCHEMA, GTK_THEME_KEY) => "Adwaita".to_string(),
+                _ => return Err(zbus::fdo::Error::Failed("unknown setting".to_string())),
+            };
+            // The portal nests the value in a second variant
+            Ok(Value::Value(Box::new(Value::from(value))).try_into().unwrap())
+        }
+    }
+
+    /// A stub bus peer serving `color_scheme`, and a monitor connected to it
+    fn connect(color_scheme: &Arc<Mutex<String>>, themes: mpsc::Sender<Theme>) -> (Connection, GnomeThemeMonitor) {
+        let (server, client) = UnixStream::pair().unwrap();
+        let portal = StubPortal {
+            color_scheme: Arc::clone(color_scheme),
+        };
+        let server = std::thread::spawn(move || {
+            Builder::async_io_unix_stream(server)
+                .p2p()
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .serve_at("/org/freedesktop/portal/desktop", portal)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = Builder::async_io_unix_stream(client).p2p().build().unwrap();
+        let monitor = GnomeThemeMonitor::with_connection(client, move |theme| {
+            let _ = themes.send(theme);
+        });
+        (server.join().unwrap(), monitor)
+    }
+
+    fn notify(server: &Connection, key: &str) {
+        server
+            .emit_signal(
+                None::<&str>,
+                "/ca/desrt/dconf/Writer/user",
+                "ca.desrt.dconf.Writer",
+                "Notify",
+                &(key, vec![""], "tag"),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn resolves_color_scheme_before_gtk_theme() {
+        assert_eq!(resolve_theme(Some("prefer-dark"), Some("Adwaita")), Some(Theme::Dark));
+        assert_eq!(resolve_theme(Some("prefer-light"), Some("Adwaita-dark")), Some(Theme::Light));
+        assert_eq!(resolve_theme(Some("default"), Some("Adwaita-dark")), Some(Theme::Dark));
+        assert_eq!(resolve_theme(None, Some("Adwaita")), Some(Theme::Light));
+        assert_eq!(resolve_theme(None, None), None);
+    }
+
+    #[test]
+    fn matches_notifications_for_watched_keys() {
+        assert!(touches_watched_keys("/org/gnome/desktop/interface/color-scheme", &[""]));
+        assert!(touches_watched_keys("/org/gnome/desktop/interface/", &["gtk-theme", "font-name"]));
+        assert!(!touches_watched_keys("/org/gnome/desktop/interface/", &["font-name"]));
+        assert!(!touches_watched_keys("/org/gnome/desktop/background/picture-uri", &[""]));
+    }
+
+    #[test]
+    fn reads_and_follows_the_color_scheme_over_the_bus() {
+        let color_scheme = Arc::new(Mutex::new("prefer-dark".to_string()));
+        let (themes_sender, themes) = mpsc::channel();
+        let (server, monitor) = connect(&color_scheme, themes_sender);
+
+        assert_eq!(monitor.read_theme().unwrap(), Theme::Dark);
+        monitor.start().unwrap();
+
+        *color_scheme.lock().unwrap() = "prefer-light".to_string();
+        notify(&server, "/org/gnome/desktop/background/picture-uri");
+        assert!(themes.recv_timeout(Duration::from_millis(300)).is_err());
+
+        notify(&server, "/org/gnome/desktop/interface/color-scheme");
+        assert_eq!(themes.recv_timeout(Duration::from_secs(5)).unwrap(), Theme::Light);
+
+        monitor.stop().unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_gsettings_without_a_portal() {
+        let dir = std::env::temp_dir().join(format!("theme-switcher-gsettings-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // Prints the GVariant text stored in the file named after the key
+        let gsettings = dir.join("gsettings");
+        std::fs::write(&gsettings, "#!/bin/sh\n[ \"$1 $2\" = \"get org.gnome.desktop.interface\" ] || exit 1\ncat \"$(dirname \"$0\")/$3\"\n").unwrap();
+        std::fs::set_permissions(&gsettings, std::os::unix::fs::PermissionsExt::from_mode(0o755)).unwrap();
+        std::fs::write(dir.join(COLOR_SCHEME_KEY), "'prefer-dark'\n").unwrap();
+        std::fs::write(dir.join(GTK_THEME_KEY), "'Adwaita'\n").unwrap();
+
+        // A bus peer serving the portal at another path, so every portal
+        // read fails
+        let (server, client) = UnixStream::pair().unwrap();
+        let portal = StubPortal {
+            color_scheme: Arc::new(Mutex::new("prefer-light".to_string())),
+        };
+        let server = std::thread::spawn(move || {
+            Builder::async_io_unix_stream(server)
+                .p2p()
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .serve_at("/elsewhere", portal)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = Builder::async_io_unix_stream(client).p2p().build().unwrap();
+        let (themes_sender, themes) = mpsc::channel();
+        let mut monitor = GnomeThemeMonitor::with_connection(client, move |theme| {
+            let _ = themes_sender.send(theme);
+        });
+        monitor.gsettings = gsettings;
+        let server = server.join().unwrap();
+
+        assert_eq!(monitor.read_theme().unwrap(), Theme::Dark);
+        monitor.start().unwrap();
+
+        std::fs::write(dir.join(COLOR_SCHEME_KEY), "'default'\n").unwrap();
+        notify(&server, "/org/gnome/desktop/interface/color-scheme");
+        assert_eq!(themes.recv_timeout(Duration::from_secs(5)).unwrap(), Theme::Light);
+
+        monitor.stop().unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
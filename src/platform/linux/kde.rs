@@ -0,0 +1,351 @@
+use crate::logging::log_error;
+use crate::{Theme, ThemeMonitor};
+use inotify::{EventMask, Inotify, WatchDescriptor, WatchMask, Watches};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// Relative luminance below which a window background counts as dark.
+/// 0.18 is roughly the perceptual midpoint (L* = 50).
+const DARK_LUMINANCE_THRESHOLD: f64 = 0.18;
+
+/// Settings from `kdeglobals` that determine the theme
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct KdeColorSettings {
+    pub color_scheme: Option<String>,
+    pub window_background: Option<(u8, u8, u8)>,
+}
+
+impl KdeColorSettings {
+    /// Parses the `[General] ColorScheme` and `[Colors:Window] BackgroundNormal` keys
+    pub fn parse(content: &str) -> Self {
+        let mut settings = Self::default();
+        let mut section = "";
+
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name;
+                continue;
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            // Keys may carry KConfig flags such as `ColorScheme[$i]`
+            let key = key.split('[').next().unwrap_or(key).trim();
+            let value = value.trim();
+
+            match (section, key) {
+                ("General", "ColorScheme") => settings.color_scheme = Some(value.to_string()),
+                ("Colors:Window", "BackgroundNormal") => settings.window_background = parse_rgb(value),
+                _ => {}
+            }
+        }
+
+        settings
+    }
+
+    /// Classifies the settings as light or dark.
+    ///
+    /// The window background luminance is authoritative since custom schemes
+    /// can have any name; the scheme name is only used when no color is set.
+    pub fn theme(&self) -> Option<Theme> {
+        if let Some((r, g, b)) = self.window_background {
+            return Some(if relative_luminance(r, g, b) < DARK_LUMINANCE_THRESHOLD {
+                Theme::Dark
+            } else {
+                Theme::Light
+            });
+        }
+
+        self.color_scheme.as_ref().map(|name| {
+            if name.to_lowercase().contains("dark") {
+                Theme::Dark
+            } else {
+                Theme::Light
+            }
+        })
+    }
+}
+
+fn parse_rgb(value: &str) -> Option<(u8, u8, u8)> {
+    let mut parts = value.split(',').map(|part| part.trim().parse::<u8>());
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(r)), Some(Ok(g)), Some(Ok(b))) => Some((r, g, b)),
+        _ => None,
+    }
+}
+
+/// WCAG relative luminance of an sRGB color
+fn relative_luminance(r: u8, g: u8, b: u8) -> f64 {
+    let linear = |channel: u8| {
+        let c = channel as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+fn read_theme_from(path: &Path) -> Result<Theme, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path).map_err(|e| format!("Unable to read {:?}: {}", path, e))?;
+    KdeColorSettings::parse(&content)
+        .theme()
+        .ok_or_else(|| format!("No color scheme found in {:?}", path).into())
+}
+
+struct Worker {
+    watches: Watches,
+    descriptor: WatchDescriptor,
+    thread: JoinHandle<()>,
+}
+
+/// Theme monitor that watches KDE Plasma's `kdeglobals` with inotify
+pub struct KdeThemeMonitor {
+    path: PathBuf,
+    callback: Arc<dyn Fn(Theme) + Send + Sync>,
+    running: Arc<AtomicBool>,
+    worker: Mutex<Option<Worker>>,
+}
+
+impl KdeThemeMonitor {
+    /// Creates a monitor for `kdeglobals` in the user's config directory
+    pub fn new<F>(callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        let path = dirs::config_dir()
+            .ok_or("Unable to determine config directory")?
+            .join("kdeglobals");
+        Ok(Self::with_path(path, callback))
+    }
+
+    /// Creates a monitor for an arbitrary `kdeglobals`-style file
+    pub fn with_path<F>(path: PathBuf, callback: F) -> Self
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Self {
+            path,
+            callback: Arc::new(callback),
+            running: Arc::new(AtomicBool::new(false)),
+            worker: Mutex::new(None),
+        }
+    }
+
+    /// Reads and classifies the current color scheme
+    pub fn read_theme(&self) -> Result<Theme, Box<dyn Error>> {
+        read_theme_from(&self.path)
+    }
+}
+
+impl ThemeMonitor for KdeThemeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        if self.running.load(Ordering::SeqCst) {
+            return Err("Monitor is already running".into());
+        }
+
+        let directory = self.path.parent().ok_or("kdeglobals path has no parent directory")?;
+        let file_name = self.path.file_name().ok_or("kdeglobals path has no file name")?.to_owned();
+
+        // KConfig replaces the file atomically, so watch the directory and
+        // filter by name instead of watching the file's inode
+        let mut inotify = Inotify::init()?;
+        let mut watches = inotify.watches();
+        let descriptor = watches.add(
+            directory,
+            WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::CREATE,
+        )?;
+
+        self.running.store(true, Ordering::SeqCst);
+
+        let path = self.path.clone();
+        let callback = Arc::clone(&self.callback);
+        let running = Arc::clone(&self.running);
+
+        let thread = std::thread::Builder::new()
+            .name("kde-monitor".to_string())
+            .spawn(move || {
+                let mut buffer = [0u8; 4096];
+                while running.load(Ordering::SeqCst) {
+                    let events = match inotify.read_events_blocking(&mut buffer) {
+                        Ok(events) => events,
+                        Err(e) => {
+                            log_error(&format!("Failed to read inotify events: {}", e));
+                            break;
+                        }
+                    };
+
+                    let mut changed = false;
+                    for event in events {
+                        // Removing the watch in stop() delivers IN_IGNORED and wakes us up
+                        if event.mask.contains(EventMask::IGNORED) {
+                            return;
+                        }
+                        if event.name == Some(file_name.as_os_str()) {
+                            changed = true;
+                        }
+                    }
+
+                    if changed {
+                        match read_theme_from(&path) {
+                            Ok(theme) => callback(theme),
+                            Err(e) => log_error(&format!("Failed to read {:?}: {}", path, e)),
+                        }
+                    }
+                }
+            })?;
+
+        *self.worker.lock().unwrap() = Some(Worker {
+            watches,
+            descriptor,
+            thread,
+        });
+
+        Ok(())
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        if let Some(mut worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.watches.remove(worker.descriptor);
+            let _ = worker.thread.join();
+        }
+
+        Ok(())
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        self.read_theme().unwrap_or(Theme::Light)
+    }
+}
+
+impl Drop for KdeThemeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Excerpt of `kdeglobals` with Plasma's Breeze color scheme
+    const BREEZE: &str = "\
+[ColorEffects:Disabled]
+Color=56,56,56
+
+[Colors:View]
+BackgroundNormal=255,255,255
+
+[Colors:Window]
+BackgroundAlternate=227,229,231
+BackgroundNormal=239,240,241
+ForegroundNormal=35,38,41
+
+[General]
+ColorScheme=BreezeLight
+Name=Breeze Light
+
+[KDE]
+LookAndFeelPackage=org.kde.breeze.desktop
+";
+
+    /// Excerpt of `kdeglobals` with Plasma's Breeze Dark color scheme
+    const BREEZE_DARK: &str = "\
+[Colors:View]
+BackgroundNormal=20,22,24
+
+[Colors:Window]
+BackgroundAlternate=41,44,48
+BackgroundNormal=32,35,38
+ForegroundNormal=252,252,252
+
+[General]
+ColorScheme=BreezeDark
+Name=Breeze Dark
+";
+
+    #[test]
+    fn parses_breeze() {
+        let settings = KdeColorSettings::parse(BREEZE);
+        assert_eq!(settings.color_scheme.as_deref(), Some("BreezeLight"));
+        assert_eq!(settings.window_background, Some((239, 240, 241)));
+        assert_eq!(settings.theme(), Some(Theme::Light));
+    }
+
+    #[test]
+    fn parses_breeze_dark() {
+        let settings = KdeColorSettings::parse(BREEZE_DARK);
+        assert_eq!(settings.color_scheme.as_deref(), Some("BreezeDark"));
+        assert_eq!(settings.window_background, Some((32, 35, 38)));
+        assert_eq!(settings.theme(), Some(Theme::Dark));
+    }
+
+    #[test]
+    fn strips_kconfig_flags_from_keys() {
+        let settings = KdeColorSettings::parse(
+            "[General]\nColorScheme[$i]=BreezeDark\n\n[Colors:Window]\nBackgroundNormal[$e]=32,35,38\n",
+        );
+        assert_eq!(settings.color_scheme.as_deref(), Some("BreezeDark"));
+        assert_eq!(settings.window_background, Some((32, 35, 38)));
+    }
+
+    #[test]
+    fn falls_back_to_the_scheme_name_without_a_window_color() {
+        let settings = KdeColorSettings::parse("[General]\nColorScheme=MyDarkScheme\n");
+        assert_eq!(settings.window_background, None);
+        assert_eq!(settings.theme(), Some(Theme::Dark));
+
+        let settings = KdeColorSettings::parse("[General]\nColorScheme=Oxygen\n");
+        assert_eq!(settings.theme(), Some(Theme::Light));
+
+        assert_eq!(KdeColorSettings::parse("[KDE]\nSingleClick=false\n").theme(), None);
+    }
+
+    #[test]
+    fn ignores_malformed_colors() {
+        for value in ["32,35", "32,35,300", "dark", "32;35;38"] {
+            let content = format!("[General]\nColorScheme=BreezeDark\n\n[Colors:Window]\nBackgroundNormal={}\n", value);
+            let settings = KdeColorSettings::parse(&content);
+            assert_eq!(settings.window_background, None, "{}", value);
+            assert_eq!(settings.theme(), Some(Theme::Dark), "{}", value);
+        }
+    }
+
+    #[test]
+    fn window_color_outweighs_the_scheme_name() {
+        let settings = KdeColorSettings::parse("[General]\nColorScheme=BreezeDark\n\n[Colors:Window]\nBackgroundNormal=239,240,241\n");
+        assert_eq!(settings.theme(), Some(Theme::Light));
+    }
+
+    #[test]
+    fn classifies_around_the_luminance_threshold() {
+        // Mid grays straddle 0.18
+        assert!(relative_luminance(117, 117, 117) < DARK_LUMINANCE_THRESHOLD);
+        assert!(relative_luminance(118, 118, 118) >= DARK_LUMINANCE_THRESHOLD);
+
+        let theme = |gray| KdeColorSettings {
+            color_scheme: None,
+            window_background: Some((gray, gray, gray)),
+        }
+        .theme();
+        assert_eq!(theme(117), Some(Theme::Dark));
+        assert_eq!(theme(118), Some(Theme::Light));
+        assert!((relative_luminance(255, 255, 255) - 1.0).abs() < 1e-9);
+        assert_eq!(relative_luminance(0, 0, 0), 0.0);
+    }
+}
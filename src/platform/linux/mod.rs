@@ -0,0 +1,156 @@
+pub mod gnome;
+pub mod kde;
+pub mod portal;
+
+pub use gnome::GnomeThemeMonitor;
+pub use kde::KdeThemeMonitor;
+pub use portal::PortalThemeMonitor;
+
+use super::registry::Backend;
+use crate::app::{Application, StartedHook};
+use crate::logging::log_info;
+use crate::error::Result;
+use crate::events::ThemeSource;
+use crate::{Theme, ThemeMonitor};
+use futures_util::future::{self, Either};
+use futures_util::{Stream, StreamExt};
+use std::error::Error;
+use std::future::Future;
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use tokio::sync::oneshot;
+
+/// Desktop backends available on Linux
+pub(super) fn backends() -> [Backend; 3] {
+    [
+        Backend {
+            name: "portal",
+            description: "XDG Desktop Portal org.freedesktop.appearance color-scheme",
+            detect: |_| PortalThemeMonitor::new(|_| {})?.read_theme().map(drop),
+            create: |_, events| {
+                let monitor = PortalThemeMonitor::new(events.callback(ThemeSource::System))?;
+                monitor.read_theme()?;
+                Ok(Box::new(monitor))
+            },
+        },
+        Backend {
+            name: "gnome",
+            description: "GNOME org.gnome.desktop.interface via dconf notifications",
+            detect: |_| GnomeThemeMonitor::new(|_| {})?.read_theme().map(drop),
+            create: |_, events| {
+                let monitor = GnomeThemeMonitor::new(events.callback(ThemeSource::System))?;
+                monitor.read_theme()?;
+                Ok(Box::new(monitor))
+            },
+        },
+        Backend {
+            name: "kde",
+            description: "KDE Plasma color scheme in kdeglobals (inotify)",
+            detect: |_| KdeThemeMonitor::new(|_| {})?.read_theme().map(drop),
+            create: |_, events| {
+                let monitor = KdeThemeMonitor::new(events.callback(ThemeSource::System))?;
+                monitor.read_theme()?;
+                Ok(Box::new(monitor))
+            },
+        },
+    ]
+}
+
+/// The portal is preferred; GNOME's GSettings may hold stale defaults on
+/// Plasma, so `kdeglobals` is tried before it in KDE sessions
+pub(super) fn system_backends() -> Vec<&'static str> {
+    if is_kde_session() {
+        vec!["portal", "kde", "gnome"]
+    } else {
+        vec!["portal", "gnome", "kde"]
+    }
+}
+
+/// Returns true when running inside a KDE Plasma session
+fn is_kde_session() -> bool {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .map(|desktop| desktop.split(':').any(|name| name.eq_ignore_ascii_case("KDE")))
+        .unwrap_or(false)
+}
+
+struct Worker {
+    stop: oneshot::Sender<()>,
+    thread: JoinHandle<()>,
+}
+
+/// The thread a D-Bus monitor handles its signal stream on, between
+/// `start` and `stop`
+#[derive(Default)]
+pub(crate) struct SignalWorker {
+    worker: Mutex<Option<Worker>>,
+}
+
+impl SignalWorker {
+    /// Passes each item of `signals` to `handle` on a new thread called `name`
+    pub fn start<S, F, Fut>(&self, name: &str, mut signals: S, mut handle: F) -> std::result::Result<(), Box<dyn Error>>
+    where
+        S: Stream + Unpin + Send + 'static,
+        F: FnMut(S::Item) -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        let mut worker = self.worker.lock().unwrap();
+        if worker.is_some() {
+            return Err("Monitor is already running".into());
+        }
+
+        let (stop, mut stop_rx) = oneshot::channel();
+        let thread = std::thread::Builder::new().name(name.to_string()).spawn(move || {
+            zbus::block_on(async move {
+                while let Either::Left((Some(signal), _)) = future::select(signals.next(), &mut stop_rx).await {
+                    handle(signal).await;
+                }
+            })
+        })?;
+
+        *worker = Some(Worker { stop, thread });
+        Ok(())
+    }
+
+    /// Stops the thread and waits for it to finish
+    pub fn stop(&self) {
+        if let Some(worker) = self.worker.lock().unwrap().take() {
+            let _ = worker.stop.send(());
+            let _ = worker.thread.join();
+        }
+    }
+}
+
+/// Linux-specific Application implementation
+pub struct LinuxApplication {
+    monitor: Box<dyn ThemeMonitor>,
+}
+
+impl LinuxApplication {
+    pub fn new(monitor: Box<dyn ThemeMonitor>) -> Self {
+        Self { monitor }
+    }
+}
+
+impl Application for LinuxApplication {
+    fn run(self: Box<Self>, on_started: StartedHook) -> Result<()> {
+        log_info("Starting theme monitor...");
+
+        // Print initial theme
+        log_info(&format!("Current theme: {}", self.monitor.get_current_theme()));
+
+        // Start monitoring
+        self.monitor.start()?;
+        log_info("Monitoring for theme changes. Press Ctrl+C to stop.");
+        on_started(self.monitor.get_current_theme());
+
+        // Events are delivered on the monitor's own thread, so the main
+        // thread only has to stay alive
+        loop {
+            std::thread::park();
+        }
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        self.monitor.get_current_theme()
+    }
+}
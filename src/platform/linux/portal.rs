@@ -0,0 +1,204 @@
+use super::SignalWorker;
+use crate::{Theme, ThemeMonitor};
+use std::error::Error;
+use std::sync::Arc;
+use zbus::blocking::Connection;
+use zbus::proxy;
+use zbus::zvariant::{OwnedValue, Value};
+
+const APPEARANCE_NAMESPACE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME_KEY: &str = "color-scheme";
+
+#[proxy(
+    interface = "org.freedesktop.portal.Settings",
+    default_service = "org.freedesktop.portal.Desktop",
+    default_path = "/org/freedesktop/portal/desktop"
+)]
+pub(super) trait Settings {
+    fn read(&self, namespace: &str, key: &str) -> zbus::Result<OwnedValue>;
+
+    #[zbus(signal)]
+    fn setting_changed(&self, namespace: &str, key: &str, value: Value<'_>) -> zbus::Result<()>;
+}
+
+/// Maps the portal `color-scheme` value onto a theme.
+///
+/// `1` means "prefer dark", while `0` (no preference) and `2` (prefer light)
+/// both resolve to light. `Settings.Read` wraps the value in an extra variant,
+/// so nested variants are unwrapped first.
+fn value_to_theme(value: &Value<'_>) -> Option<Theme> {
+    match value {
+        Value::Value(inner) => value_to_theme(inner),
+        Value::U32(1) => Some(Theme::Dark),
+        Value::U32(_) => Some(Theme::Light),
+        _ => None,
+    }
+}
+
+/// Theme monitor backed by the XDG Desktop Portal `org.freedesktop.portal.Settings` interface
+pub struct PortalThemeMonitor {
+    connection: Connection,
+    callback: Arc<dyn Fn(Theme) + Send + Sync>,
+    signals: SignalWorker,
+}
+
+impl PortalThemeMonitor {
+    /// Creates a monitor connected to the user's session bus
+    pub fn new<F>(callback: F) -> Result<Self, Box<dyn Error>>
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Ok(Self::with_connection(Connection::session()?, callback))
+    }
+
+    /// Creates a monitor on an existing connection, e.g. a private bus running a stub portal
+    pub fn with_connection<F>(connection: Connection, callback: F) -> Self
+    where
+        F: Fn(Theme) + Send + Sync + 'static,
+    {
+        Self {
+            connection,
+            callback: Arc::new(callback),
+            signals: SignalWorker::default(),
+        }
+    }
+
+    /// Reads the current color scheme from the portal
+    pub fn read_theme(&self) -> Result<Theme, Box<dyn Error>> {
+        let proxy = SettingsProxyBlocking::new(&self.connection)?;
+        let value = proxy.read(APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY)?;
+        value_to_theme(&value)
+            .ok_or_else(|| format!("Unexpected {} value: {:?}", COLOR_SCHEME_KEY, value).into())
+    }
+}
+
+impl ThemeMonitor for PortalThemeMonitor {
+    fn start(&self) -> Result<(), Box<dyn Error>> {
+        // Subscribe before returning so no change between start() and the
+        // worker thread spinning up is lost
+        let connection = self.connection.inner().clone();
+        let changes = zbus::block_on(async {
+            let proxy = SettingsProxy::new(&connection).await?;
+            proxy
+                .receive_setting_changed_with_args(&[
+                    (0, APPEARANCE_NAMESPACE),
+                    (1, COLOR_SCHEME_KEY),
+                ])
+                .await
+        })?;
+
+        let callback = Arc::clone(&self.callback);
+        self.signals.start("portal-monitor", changes, move |signal| {
+            let theme = signal.args().ok().and_then(|args| value_to_theme(args.value()));
+            if let Some(theme) = theme {
+                callback(theme);
+            }
+            std::future::ready(())
+        })
+    }
+
+    fn stop(&self) -> Result<(), Box<dyn Error>> {
+        self.signals.stop();
+        Ok(())
+    }
+
+    fn get_current_theme(&self) -> Theme {
+        self.read_theme().unwrap_or(Theme::Light)
+    }
+}
+
+impl Drop for PortalThemeMonitor {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixStream;
+    use std::sync::{Mutex, mpsc};
+    use std::time::Duration;
+    use zbus::blocking::connection::Builder;
+
+    /// Serves the appearance namespace like the desktop portal
+    struct StubPortal {
+        color_scheme: Arc<Mutex<u32>>,
+    }
+
+    #[zbus::interface(name = "org.freedesktop.portal.Settings")]
+    impl StubPortal {
+        fn read(&self, namespace: &str, key: &str) -> zbus::fdo::Result<OwnedValue> {
+            if (namespace, key) != (APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY) {
+                return Err(zbus::fdo::Error::Failed("unknown setting".to_string()));
+            }
+            let value = *self.color_scheme.lock().unwrap();
+            Ok(Value::Value(Box::new(Value::from(value))).try_into().unwrap())
+        }
+    }
+
+    /// A stub bus peer serving `color_scheme`, and a monitor connected to it
+    fn connect(color_scheme: &Arc<Mutex<u32>>, themes: mpsc::Sender<Theme>) -> (Connection, PortalThemeMonitor) {
+        let (server, client) = UnixStream::pair().unwrap();
+        let portal = StubPortal {
+            color_scheme: Arc::clone(color_scheme),
+        };
+        let server = std::thread::spawn(move || {
+            Builder::async_io_unix_stream(server)
+                .p2p()
+                .server(zbus::Guid::generate())
+                .unwrap()
+                .serve_at("/org/freedesktop/portal/desktop", portal)
+                .unwrap()
+                .build()
+                .unwrap()
+        });
+        let client = Builder::async_io_unix_stream(client).p2p().build().unwrap();
+        let monitor = PortalThemeMonitor::with_connection(client, move |theme| {
+            let _ = themes.send(theme);
+        });
+        (server.join().unwrap(), monitor)
+    }
+
+    fn setting_changed(server: &Connection, namespace: &str, key: &str, value: u32) {
+        server
+            .emit_signal(
+                None::<&str>,
+                "/org/freedesktop/portal/desktop",
+                "org.freedesktop.portal.Settings",
+                "SettingChanged",
+                &(namespace, key, Value::from(value)),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn maps_color_scheme_values() {
+        assert_eq!(value_to_theme(&Value::U32(1)), Some(Theme::Dark));
+        assert_eq!(value_to_theme(&Value::U32(0)), Some(Theme::Light));
+        assert_eq!(value_to_theme(&Value::U32(2)), Some(Theme::Light));
+        assert_eq!(value_to_theme(&Value::Value(Box::new(Value::U32(1)))), Some(Theme::Dark));
+        assert_eq!(value_to_theme(&Value::from("prefer-dark")), None);
+    }
+
+    #[test]
+    fn reads_and_follows_the_color_scheme_over_the_bus() {
+        let color_scheme = Arc::new(Mutex::new(1));
+        let (themes_sender, themes) = mpsc::channel();
+        let (server, monitor) = connect(&color_scheme, themes_sender);
+
+        assert_eq!(monitor.read_theme().unwrap(), Theme::Dark);
+        monitor.start().unwrap();
+
+        setting_changed(&server, "org.gnome.desktop.interface", COLOR_SCHEME_KEY, 1);
+        setting_changed(&server, APPEARANCE_NAMESPACE, "accent-color", 1);
+        assert!(themes.recv_timeout(Duration::from_millis(300)).is_err());
+
+        *color_scheme.lock().unwrap() = 2;
+        setting_changed(&server, APPEARANCE_NAMESPACE, COLOR_SCHEME_KEY, 2);
+        assert_eq!(themes.recv_timeout(Duration::from_secs(5)).unwrap(), Theme::Light);
+        assert_eq!(monitor.get_current_theme(), Theme::Light);
+
+        monitor.stop().unwrap();
+    }
+}
@@ -1,6 +1,5 @@
-use crate::app::Application;
+use crate::app::{Application, StartedHook};
 use crate::error::{Result, ThemeSwitcherError};
-use crate::handlers::ThemeHandler;
 use crate::{Theme, ThemeMonitor};
 use cocoa::appkit::{NSApp, NSApplication, NSApplicationActivationPolicyAccessory};
 use cocoa::base::{BOOL, YES, id, nil};
@@ -205,23 +204,15 @@ impl Drop for MacOSThemeMonitor {
 
 /// macOS-specific Application implementation
 pub struct MacOSApplication {
-    monitor: MacOSThemeMonitor,
+    monitor: Box<dyn ThemeMonitor>,
 }
 
 impl MacOSApplication {
-    pub fn new(handler: Arc<dyn ThemeHandler>) -> Result<Self> {
+    /// Run an arbitrary monitor inside the AppKit event loop
+    pub fn with_monitor(monitor: Box<dyn ThemeMonitor>) -> Result<Self> {
         Self::initialize()?;
-
-        let monitor = MacOSThemeMonitor::new(move |theme| {
-            handler.on_theme_change(theme);
-        });
-
         Ok(Self { monitor })
     }
-    
-    pub fn get_current_theme(&self) -> Theme {
-        self.monitor.get_current_theme()
-    }
 
     fn initialize() -> Result<()> {
         unsafe {
@@ -238,8 +229,8 @@ impl MacOSApplication {
 }
 
 impl Application for MacOSApplication {
-    fn run(self: Box<Self>) -> Result<()> {
-        use crate::config::log_info;
+    fn run(self: Box<Self>, on_started: StartedHook) -> Result<()> {
+        use crate::logging::log_info;
         
         log_info("Starting theme monitor...");
 
@@ -249,6 +240,7 @@ impl Application for MacOSApplication {
         // Start monitoring
         self.monitor.start()?;
         log_info("Monitoring for theme changes. Press Ctrl+C to stop.");
+        on_started(self.monitor.get_current_theme());
 
         // Run the macOS event loop
         unsafe {
@@ -258,4 +250,8 @@ impl Application for MacOSApplication {
 
         Ok(())
     }
+
+    fn get_current_theme(&self) -> Theme {
+        self.monitor.get_current_theme()
+    }
 }
@@ -4,23 +4,52 @@ pub mod macos;
 #[cfg(target_os = "macos")]
 pub use macos::{MacOSThemeMonitor, MacOSApplication};
 
+#[cfg(target_os = "linux")]
+pub mod linux;
+
+#[cfg(target_os = "linux")]
+pub use linux::{GnomeThemeMonitor, KdeThemeMonitor, LinuxApplication, PortalThemeMonitor};
+
+pub mod registry;
+
+use crate::config_file::Config;
 use crate::error::Result;
-use crate::handlers::ThemeHandler;
 use crate::app::Application;
-use std::sync::Arc;
+use crate::events::EventSender;
+use crate::ThemeMonitor;
 
-/// Factory function to create platform-specific application
-pub fn create_application(handler: Arc<dyn ThemeHandler>) -> Result<Box<dyn Application>> {
+/// Factory function to create the application for the configured backends.
+/// Theme changes are sent to `events`, see [`crate::events::channel`].
+pub fn create_application(
+    events: EventSender,
+    config: Option<&Config>,
+) -> Result<Box<dyn Application>> {
+    let default_config = Config::default();
+    let config = config.unwrap_or(&default_config);
+    
+    let (_, monitor) = registry::select(config, events)?;
+    
+    wrap_monitor(monitor)
+}
+
+/// Run a monitor inside the platform's event loop
+#[allow(unused_variables)]
+fn wrap_monitor(monitor: Box<dyn ThemeMonitor>) -> Result<Box<dyn Application>> {
     #[cfg(target_os = "macos")]
     {
-        Ok(Box::new(MacOSApplication::new(handler)?))
+        Ok(Box::new(MacOSApplication::with_monitor(monitor)?))
+    }
+    
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(LinuxApplication::new(monitor)))
     }
     
-    #[cfg(not(target_os = "macos"))]
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
     {
         use crate::error::ThemeSwitcherError;
         Err(ThemeSwitcherError::PlatformError(
             "This platform is not currently supported".to_string()
         ))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,316 @@
+//! Named theme monitor backends and runtime selection between them
+
+use crate::logging::log_info;
+use crate::config_file::Config;
+use crate::error::{Result, ThemeSwitcherError};
+use crate::monitors::{
+    AmbientThemeMonitor, CommandThemeMonitor, CompositeMonitor, FileThemeMonitor, Schedule,
+    ScheduleThemeMonitor, SolarSchedule,
+};
+use crate::events::{EventSender, ThemeSource};
+use crate::ThemeMonitor;
+use std::error::Error;
+
+/// Name that expands to the desktop backends of the current platform
+pub const SYSTEM: &str = "system";
+
+/// Name of the backend that combines other backends
+pub const COMPOSITE: &str = "composite";
+
+type DetectFn = fn(&Config) -> std::result::Result<(), Box<dyn Error>>;
+type CreateFn = fn(&Config, EventSender) -> std::result::Result<Box<dyn ThemeMonitor>, Box<dyn Error>>;
+
+/// A named way of producing theme events
+#[derive(Clone, Copy)]
+pub struct Backend {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub(super) detect: DetectFn,
+    pub(super) create: CreateFn,
+}
+
+impl Backend {
+    /// Checks whether the backend can run on this machine with this config
+    pub fn detect(&self, config: &Config) -> std::result::Result<(), Box<dyn Error>> {
+        (self.detect)(config)
+    }
+
+    /// Creates the backend's monitor, reporting changes to `events`
+    pub fn create(
+        &self,
+        config: &Config,
+        events: EventSender,
+    ) -> std::result::Result<Box<dyn ThemeMonitor>, Box<dyn Error>> {
+        (self.create)(config, events)
+    }
+}
+
+/// All backends compiled into this build
+pub fn backends() -> Vec<Backend> {
+    let mut backends = Vec::new();
+
+    #[cfg(target_os = "macos")]
+    backends.push(Backend {
+        name: "macos",
+        description: "macOS effectiveAppearance (AppKit key-value observing)",
+        detect: |_| Ok(()),
+        create: |_, events| {
+            Ok(Box::new(super::MacOSThemeMonitor::new(events.callback(ThemeSource::System))))
+        },
+    });
+
+    #[cfg(target_os = "linux")]
+    backends.extend(super::linux::backends());
+
+    backends.extend([
+        Backend {
+            name: "file",
+            description: "Contents of the state file configured in [file]",
+            detect: |_| Ok(()),
+            create: |config, events| {
+                let path = config.file.path();
+                log_info(&format!("Following theme file: {:?}", path));
+                Ok(Box::new(FileThemeMonitor::new(path, events.callback(ThemeSource::Manual))))
+            },
+        },
+        Backend {
+            name: "schedule",
+            description: "Fixed switch times configured in [schedule]",
+            detect: |_| Ok(()),
+            create: |config, events| {
+                let schedule = Schedule::from_config(&config.schedule);
+                Ok(Box::new(ScheduleThemeMonitor::new(schedule, events.callback(ThemeSource::Schedule))))
+            },
+        },
+        Backend {
+            name: "solar",
+            description: "Sunrise and sunset for the location configured in [solar]",
+            detect: |config| Ok(SolarSchedule::from_config(&config.solar).map(drop)?),
+            create: |config, events| {
+                let schedule = SolarSchedule::from_config(&config.solar)?;
+                Ok(Box::new(ScheduleThemeMonitor::new(schedule, events.callback(ThemeSource::Schedule))))
+            },
+        },
+        Backend {
+            name: "ambient",
+            description: "Ambient light sensor configured in [ambient]",
+            detect: |config| AmbientThemeMonitor::new(config.ambient.clone(), |_| {}).map(drop),
+            create: |config, events| {
+                let monitor = AmbientThemeMonitor::new(config.ambient.clone(), events.callback(ThemeSource::Sensor))?;
+                log_info(&format!("Using light sensor: {:?}", monitor.sensor().device()));
+                Ok(Box::new(monitor))
+            },
+        },
+        Backend {
+            name: "command",
+            description: "Probe command configured in [command]",
+            detect: |config| CommandThemeMonitor::new(config.command.clone(), |_| {}).map(drop),
+            create: |config, events| {
+                Ok(Box::new(CommandThemeMonitor::new(config.command.clone(), events.callback(ThemeSource::Command))?))
+            },
+        },
+        Backend {
+            name: COMPOSITE,
+            description: "Sources configured in [composite], combined by priority or policy",
+            detect: |config| {
+                if config.composite.sources.is_empty() {
+                    return Err("[composite] has no sources".into());
+                }
+                Ok(())
+            },
+            create: create_composite,
+        },
+    ]);
+
+    backends
+}
+
+/// Looks up a backend by name
+pub fn find(name: &str) -> Option<Backend> {
+    backends().into_iter().find(|backend| backend.name == name)
+}
+
+/// Desktop backends of the current platform, most preferred first
+pub fn system_backends() -> Vec<&'static str> {
+    #[cfg(target_os = "macos")]
+    {
+        vec!["macos"]
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        super::linux::system_backends()
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "linux")))]
+    {
+        Vec::new()
+    }
+}
+
+/// Resolves the configured preference list into backends, expanding `system`
+pub fn resolve(preference: &[String]) -> Result<Vec<Backend>> {
+    let mut resolved: Vec<Backend> = Vec::new();
+
+    for name in preference {
+        let names = if name == SYSTEM {
+            system_backends()
+        } else {
+            vec![name.as_str()]
+        };
+
+        for name in names {
+            let backend = find(name).ok_or_else(|| {
+                ThemeSwitcherError::config(format!(
+                    "Unknown theme backend {:?} (run `theme-switcher backends` to list them)",
+                    name
+                ))
+            })?;
+            if !resolved.iter().any(|b| b.name == backend.name) {
+                resolved.push(backend);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Walks the configured preference list and creates the first backend that
+/// is available, falling back to the next one on failure
+pub fn select(config: &Config, events: EventSender) -> Result<(Backend, Box<dyn ThemeMonitor>)> {
+    select_from(&config.general.monitor, config, events)
+}
+
+/// Like [`select`], for an explicit preference list
+pub fn select_from(
+    preference: &[String],
+    config: &Config,
+    events: EventSender,
+) -> Result<(Backend, Box<dyn ThemeMonitor>)> {
+    create_first(resolve(preference)?, config, events)
+}
+
+/// Creates the first of `candidates` that is available
+fn create_first(
+    candidates: Vec<Backend>,
+    config: &Config,
+    events: EventSender,
+) -> Result<(Backend, Box<dyn ThemeMonitor>)> {
+    let mut errors = Vec::new();
+
+    for backend in candidates {
+        let created = backend
+            .detect(config)
+            .and_then(|_| backend.create(config, events.clone()));
+
+        match created {
+            Ok(monitor) => {
+                log_info(&format!("Using {} theme backend", backend.name));
+                return Ok((backend, monitor));
+            }
+            Err(e) => {
+                log_info(&format!("The {} theme backend is unavailable: {}", backend.name, e));
+                errors.push(format!("{}: {}", backend.name, e));
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        return Err(ThemeSwitcherError::BackendUnavailable(
+            "No theme backend is supported on this platform".to_string(),
+        ));
+    }
+
+    Err(ThemeSwitcherError::BackendUnavailable(format!(
+        "No theme backend available ({})",
+        errors.join("; ")
+    )))
+}
+
+fn create_composite(
+    config: &Config,
+    events: EventSender,
+) -> std::result::Result<Box<dyn ThemeMonitor>, Box<dyn Error>> {
+    let mut composite = CompositeMonitor::new(config.composite.policy, events);
+
+    for source in &config.composite.sources {
+        if source.monitor.iter().any(|name| name == COMPOSITE) {
+            return Err("[composite] sources cannot include the composite backend".into());
+        }
+
+        composite.add_source(source.priority, source.only, |events| {
+            let (_, monitor) = select_from(&source.monitor, config, events)?;
+            Ok(monitor)
+        })?;
+    }
+
+    if composite.is_empty() {
+        return Err("[composite] has no sources".into());
+    }
+
+    Ok(Box::new(composite))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(backends: &[Backend]) -> Vec<&'static str> {
+        backends.iter().map(|backend| backend.name).collect()
+    }
+
+    fn preference(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// A backend that detects fine but fails to start
+    fn broken() -> Backend {
+        Backend {
+            name: "broken",
+            description: "Always fails to start",
+            detect: |_| Ok(()),
+            create: |_, _| Err("no display".into()),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_backends() {
+        let Err(ThemeSwitcherError::Config { message, .. }) = resolve(&preference(&["file", "nope"])) else {
+            panic!("expected a config error");
+        };
+        assert!(message.starts_with("Unknown theme backend \"nope\""), "{}", message);
+    }
+
+    #[test]
+    fn expands_system_in_place_without_duplicates() {
+        let system = system_backends();
+        assert_eq!(names(&resolve(&preference(&[SYSTEM])).unwrap()), system);
+
+        let mut expected = vec!["file"];
+        expected.extend(&system);
+        expected.push("schedule");
+        assert_eq!(
+            names(&resolve(&preference(&["file", SYSTEM, "file", "schedule", SYSTEM])).unwrap()),
+            expected
+        );
+    }
+
+    #[test]
+    fn falls_back_when_a_backend_fails_to_start() {
+        let config = Config::default();
+        let candidates = vec![broken(), find("command").unwrap(), find("file").unwrap()];
+        let (backend, _) = create_first(candidates, &config, EventSender::new(|_| {})).unwrap();
+        assert_eq!(backend.name, "file");
+    }
+
+    #[test]
+    fn reports_every_failure_when_nothing_starts() {
+        let config = Config::default();
+        let candidates = vec![broken(), find("command").unwrap()];
+        let Err(ThemeSwitcherError::BackendUnavailable(message)) = create_first(candidates, &config, EventSender::new(|_| {})) else {
+            panic!("expected no backend to be available");
+        };
+        assert!(message.contains("broken: no display"), "{}", message);
+        assert!(message.contains("command: [command] probe must be set"), "{}", message);
+    }
+}
@@ -0,0 +1,68 @@
+use crate::events::{ThemeEvent, ThemeSource};
+use crate::handlers::HandlerReport;
+use crate::Theme;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// `$XDG_STATE_HOME/theme-switcher/state.json`, falling back to
+/// `~/.local/state` where the platform has no state directory
+pub fn default_state_file() -> PathBuf {
+    dirs::state_dir()
+        .or_else(|| dirs::home_dir().map(|home| home.join(".local/state")))
+        .unwrap_or_default()
+        .join("theme-switcher/state.json")
+}
+
+/// The last theme the handlers were run for, persisted across restarts
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct AppliedState {
+    pub theme: Theme,
+    pub source: ThemeSource,
+    pub timestamp: DateTime<Local>,
+    /// Outcome of each handler in that run
+    pub handlers: Vec<HandlerReport>,
+}
+
+impl AppliedState {
+    pub fn new(event: &ThemeEvent, handlers: Vec<HandlerReport>) -> Self {
+        Self {
+            theme: event.theme,
+            source: event.source,
+            timestamp: Local::now(),
+            handlers,
+        }
+    }
+
+    /// True if every handler applied the theme
+    pub fn succeeded(&self) -> bool {
+        self.handlers.iter().all(|report| report.outcome.is_success())
+    }
+
+    /// The theme that is known to be fully applied, if any
+    pub fn applied_theme(&self) -> Option<Theme> {
+        self.succeeded().then_some(self.theme)
+    }
+
+    /// Reads the state, returning None if it was never written
+    pub fn load(path: &Path) -> Result<Option<Self>, Box<dyn Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Writes the state atomically, creating its directory if needed
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let temp = path.with_extension("json.tmp");
+        std::fs::write(&temp, serde_json::to_string_pretty(self)? + "\n")?;
+        std::fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
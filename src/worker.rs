@@ -0,0 +1,129 @@
+use crate::logging::log_info;
+use crate::handlers::{HandlerOutcome, HandlerReport, ThemeHandler};
+use crate::ThemeEvent;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// Flag telling a handler run to give up, shared between the worker and the
+/// run
+#[derive(Debug, Clone, Default)]
+pub struct Cancellation(Arc<AtomicBool>);
+
+impl Cancellation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    /// The latest event not yet picked up; older ones are superseded
+    pending: Option<ThemeEvent>,
+    /// Cancels the run in progress, if any
+    running: Option<Cancellation>,
+    closed: bool,
+}
+
+/// Runs a handler on a dedicated thread, one event at a time.
+///
+/// Submitting an event replaces any event still waiting and cancels the run
+/// in progress, so only the latest theme is applied.
+pub struct HandlerWorker {
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HandlerWorker {
+    /// Starts the worker; `on_done` is called on the worker thread after each
+    /// run, including cancelled ones
+    pub fn spawn<F>(handler: Arc<dyn ThemeHandler>, on_done: F) -> Self
+    where
+        F: Fn(&ThemeEvent, Vec<HandlerReport>) + Send + 'static,
+    {
+        let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+        let worker_queue = Arc::clone(&queue);
+
+        let thread = thread::spawn(move || {
+            let (lock, condvar) = &*worker_queue;
+            loop {
+                let (event, cancel) = {
+                    let mut queue = lock.lock().unwrap();
+                    while queue.pending.is_none() && !queue.closed {
+                        queue = condvar.wait(queue).unwrap();
+                    }
+                    let Some(event) = queue.pending.take().filter(|_| !queue.closed) else {
+                        return;
+                    };
+                    let cancel = Cancellation::new();
+                    queue.running = Some(cancel.clone());
+                    (event, cancel)
+                };
+
+                // A panicking handler must not take the worker down with it
+                let reports = panic::catch_unwind(AssertUnwindSafe(|| handler.apply(&event, &cancel)))
+                    .unwrap_or_else(|_| {
+                        let error = "Handler panicked".to_string();
+                        vec![HandlerReport::new(handler.name(), HandlerOutcome::Failed { error })]
+                    });
+                lock.lock().unwrap().running = None;
+                on_done(&event, reports);
+            }
+        });
+
+        Self {
+            queue,
+            thread: Some(thread),
+        }
+    }
+
+    /// Queues an event, superseding whatever is waiting or running
+    pub fn submit(&self, event: ThemeEvent) {
+        let (lock, condvar) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+
+        if let Some(superseded) = queue.pending.replace(event) {
+            log_info(&format!("Skipping superseded {} theme change", superseded.theme));
+        }
+        if let Some(ref running) = queue.running {
+            log_info("Cancelling handlers for the superseded theme change");
+            running.cancel();
+        }
+        condvar.notify_one();
+    }
+
+    /// Drops waiting events, cancels the run in progress and waits for the
+    /// worker to finish
+    pub fn shutdown(mut self) {
+        self.close();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+
+    fn close(&self) {
+        let (lock, condvar) = &*self.queue;
+        let mut queue = lock.lock().unwrap();
+        queue.closed = true;
+        queue.pending = None;
+        if let Some(ref running) = queue.running {
+            running.cancel();
+        }
+        condvar.notify_one();
+    }
+}
+
+impl Drop for HandlerWorker {
+    fn drop(&mut self) {
+        self.close();
+    }
+}